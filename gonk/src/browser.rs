@@ -1,4 +1,4 @@
-use gonk_core::{vdb::Database, Album};
+use gonk_core::{db::SongId, vdb::Database, Album};
 use gonk_core::{Index, Song};
 use winter::*;
 
@@ -12,8 +12,8 @@ pub enum Mode {
 pub struct Browser {
     artists: Index<String>,
     albums: Index<Album>,
-    ///Title, (disc, track)
-    songs: Index<(String, (u8, u8))>,
+    ///Title, ID
+    songs: Index<(String, SongId)>,
     pub mode: Mode,
 }
 
@@ -25,20 +25,9 @@ impl Browser {
         let mut songs = Index::default();
 
         if let Some(artist) = artists.selected() {
-            albums = Index::from(db.albums_by_artist(artist));
+            albums = Index::from(db.albums_by_artist(artist).unwrap_or_default());
             if let Some(album) = albums.selected() {
-                songs = Index::from(
-                    album
-                        .songs
-                        .iter()
-                        .map(|song| {
-                            (
-                                format!("{}. {}", song.track_number, song.title),
-                                (song.disc_number, song.track_number),
-                            )
-                        })
-                        .collect::<Vec<(String, (u8, u8))>>(),
-                );
+                songs = Index::from(song_list(album));
             }
         }
 
@@ -120,7 +109,17 @@ pub fn draw(
     }
 
     let artists: Vec<_> = browser.artists.iter().map(|a| lines!(a)).collect();
-    let albums: Vec<_> = browser.albums.iter().map(|a| lines!(&a.title)).collect();
+    let albums: Vec<_> = browser
+        .albums
+        .iter()
+        .map(|a| match a.completeness() {
+            //Show how many tracks are missing.
+            Some((found, expected)) if found != expected => {
+                lines!(&a.title, text!(" ({found}/{expected})").dim())
+            }
+            _ => lines!(&a.title),
+        })
+        .collect();
     let songs: Vec<_> = browser.songs.iter().map(|(s, _)| lines!(s)).collect();
 
     fn list<'a>(title: &'static str, items: Vec<Line<'a>>, use_symbol: bool) -> List<'a> {
@@ -148,10 +147,42 @@ pub fn refresh(browser: &mut Browser, db: &Database) {
     update_albums(browser, db);
 }
 
+///Reload after the database changed, keeping the selection where possible.
+pub fn reload(browser: &mut Browser, db: &Database) {
+    let artist = browser.artists.selected().cloned();
+    let album = browser
+        .albums
+        .selected()
+        .map(|album| (album.title.clone(), album.folder.clone()));
+    let song = browser.songs.selected().map(|(_, id)| *id);
+
+    let artists: Vec<String> = db.artists().into_iter().cloned().collect();
+    let index = artists.iter().position(|a| Some(a) == artist.as_ref());
+    if index.is_none() {
+        browser.mode = Mode::Artist;
+    }
+    browser.artists = Index::new(artists, Some(index.unwrap_or(0)));
+    update_albums(browser, db);
+
+    if let Some(i) = album.and_then(|(title, folder)| {
+        browser
+            .albums
+            .iter()
+            .position(|a| a.title == title && a.folder == folder)
+    }) {
+        browser.albums.select(Some(i));
+        update_songs(browser);
+    }
+
+    if let Some(i) = song.and_then(|id| browser.songs.iter().position(|(_, s)| *s == id)) {
+        browser.songs.select(Some(i));
+    }
+}
+
 pub fn update(browser: &mut Browser, db: &Database) {
     match browser.mode {
         Mode::Artist => update_albums(browser, db),
-        Mode::Album => update_songs(browser, db),
+        Mode::Album => update_songs(browser),
         Mode::Song => (),
     }
 }
@@ -159,47 +190,50 @@ pub fn update(browser: &mut Browser, db: &Database) {
 pub fn update_albums(browser: &mut Browser, db: &Database) {
     //Update the album based on artist selection
     if let Some(artist) = browser.artists.selected() {
-        browser.albums = Index::from(db.albums_by_artist(artist));
-        update_songs(browser, db);
+        browser.albums = Index::from(db.albums_by_artist(artist).unwrap_or_default());
+        update_songs(browser);
     }
 }
 
-pub fn update_songs(browser: &mut Browser, db: &Database) {
-    if let Some(artist) = browser.artists.selected() {
+fn song_list(album: &Album) -> Vec<(String, SongId)> {
+    album
+        .songs
+        .iter()
+        .map(|song| (format!("{}. {}", song.track_number, song.title), song.id))
+        .collect()
+}
+
+///Albums are looked up by selection since compilations in different folders can share a title.
+pub fn update_songs(browser: &mut Browser) {
+    if browser.artists.selected().is_some() {
         if let Some(album) = browser.albums.selected() {
-            let songs: Vec<(String, (u8, u8))> = db
-                .album(artist, &album.title)
-                .songs
-                .iter()
-                .map(|song| {
-                    (
-                        format!("{}. {}", song.track_number, song.title),
-                        (song.disc_number, song.track_number),
-                    )
-                })
-                .collect();
-            browser.songs = Index::from(songs);
+            browser.songs = Index::from(song_list(album));
         }
     }
 }
 
+///The songs in the selection, empty if nothing is selected.
 pub fn get_selected(browser: &Browser, db: &Database) -> Vec<Song> {
-    if let Some(artist) = browser.artists.selected() {
-        if let Some(album) = browser.albums.selected() {
-            if let Some((_, (disc, number))) = browser.songs.selected() {
-                return match browser.mode {
-                    Mode::Artist => db
-                        .albums_by_artist(artist)
-                        .iter()
-                        .flat_map(|album| album.songs.iter().map(|song| song.clone().clone()))
-                        .collect(),
-                    Mode::Album => db.album(artist, &album.title).songs.to_vec(),
-                    Mode::Song => {
-                        vec![db.song(artist, &album.title, *disc, *number).clone()]
-                    }
-                };
-            }
-        }
+    match browser.mode {
+        Mode::Artist => browser
+            .artists
+            .selected()
+            .and_then(|artist| db.albums_by_artist(artist))
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|album| album.songs.iter().cloned())
+            .collect(),
+        Mode::Album => browser
+            .albums
+            .selected()
+            .map(|album| album.songs.clone())
+            .unwrap_or_default(),
+        Mode::Song => browser
+            .songs
+            .selected()
+            .and_then(|(_, id)| db.song(*id))
+            .cloned()
+            .into_iter()
+            .collect(),
     }
-    todo!()
 }
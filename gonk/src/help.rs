@@ -5,7 +5,7 @@ use winter::*;
 //TODO: Add scrolling to the help menu.
 //TODO: Improve visability, it's hard to tell which option matches which command.
 //TODO: Do I have a widget for adding lines?
-pub static HELP: LazyLock<[Row; 32]> = LazyLock::new(|| {
+pub static HELP: LazyLock<[Row; 34]> = LazyLock::new(|| {
     [
         row!["Move Up".fg(Cyan), "K / UP"],
         row!["Move Down".fg(Cyan), "J / Down"],
@@ -28,6 +28,8 @@ pub static HELP: LazyLock<[Row; 32]> = LazyLock::new(|| {
         row!["Browser".fg(Blue), "2"],
         row!["Playlists".fg(Blue), "3"],
         row!["Settings".fg(Blue), "4"],
+        row!["Scan report".fg(Blue), "5"],
+        row!["Lyrics".fg(Blue), "6"],
         row!["Search".fg(Blue), "/"],
         row!["Exit Search".fg(Blue), "Escape | Tab"],
         row!["Select all".fg(Cyan), "Control + A"],
@@ -0,0 +1,123 @@
+use crate::TITLE;
+use gonk_core::{db::SongId, lyrics, vdb::Database, Index, Song};
+use winter::*;
+
+///Lyrics of the playing song, synced lyrics follow along with playback.
+pub struct Lyrics {
+    ///The song the lyrics were parsed for.
+    id: Option<SongId>,
+    ///The text the lyrics were parsed from, so edited `.lrc` files are parsed again.
+    text: String,
+    lyrics: lyrics::Lyrics,
+    ///First line shown when the lyrics aren't synced.
+    scroll: usize,
+}
+
+impl Lyrics {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            text: String::new(),
+            lyrics: lyrics::Lyrics::default(),
+            scroll: 0,
+        }
+    }
+}
+
+pub fn up(lyrics: &mut Lyrics, amount: usize) {
+    lyrics.scroll = lyrics.scroll.saturating_sub(amount);
+}
+
+pub fn down(lyrics: &mut Lyrics, amount: usize) {
+    let last = lyrics.lyrics.lines.len().saturating_sub(1);
+    lyrics.scroll = (lyrics.scroll + amount).min(last);
+}
+
+pub fn draw(
+    lyrics: &mut Lyrics,
+    area: winter::Rect,
+    buf: &mut winter::Buffer,
+    mouse: Option<(u16, u16)>,
+    songs: &Index<Song>,
+    db: &Database,
+) {
+    //The queue doesn't store lyrics, they're looked up in the database.
+    let song = songs
+        .selected()
+        .map(|song| db.song(song.id).unwrap_or(song));
+    let id = song.map(|song| song.id);
+    let text = song.map_or("", |song| song.lyrics.as_str());
+    if id != lyrics.id {
+        lyrics.id = id;
+        lyrics.scroll = 0;
+    }
+    if text != lyrics.text {
+        lyrics.text = text.to_string();
+        lyrics.lyrics = lyrics::Lyrics::parse(text);
+        lyrics.scroll = lyrics
+            .scroll
+            .min(lyrics.lyrics.lines.len().saturating_sub(1));
+    }
+
+    let title = song.map_or("Lyrics", |song| song.title.as_str());
+    let block = block().title(title.bold()).title_margin(1);
+    let lines = &lyrics.lyrics.lines;
+
+    if lines.is_empty() {
+        let message = if song.is_some() {
+            "No lyrics found."
+        } else {
+            "Nothing is playing."
+        };
+        return lines!(message).block(block).draw(area, buf);
+    }
+
+    let elapsed = gonk_player::elapsed();
+    let current = lyrics.lyrics.current(elapsed);
+    let height = area.height.saturating_sub(2) as usize;
+
+    //Keep the current line in the middle.
+    let start = match current {
+        Some(current) => current.saturating_sub(height / 2),
+        None if lyrics.lyrics.is_synced() => 0,
+        None => lyrics.scroll,
+    }
+    .min(lines.len().saturating_sub(height));
+
+    let rows: Vec<Row> = lines[start..]
+        .iter()
+        .take(height)
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(start + i) == current {
+                let sung = line.sung(elapsed);
+                row![lines!(
+                    line.text[..sung].fg(TITLE).bold(),
+                    line.text[sung..].fg(TITLE)
+                )]
+            } else if lyrics.lyrics.is_synced() {
+                row![line.text.as_str().dim()]
+            } else {
+                row![line.text.as_str()]
+            }
+        })
+        .collect();
+
+    table(rows, &[Constraint::Percentage(100)])
+        .block(block)
+        .draw(area, buf, None);
+
+    //Clicking a line seeks to it.
+    if let Some((_, y)) = mouse {
+        //Rows start below the border.
+        let Some(row) = y.checked_sub(area.y + 1).map(|row| row as usize) else {
+            return;
+        };
+        if row >= height {
+            return;
+        }
+        if let Some(time) = lines.get(start + row).and_then(|line| line.time) {
+            gonk_player::seek(time.as_secs_f32());
+        }
+    }
+}
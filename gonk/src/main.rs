@@ -1,9 +1,11 @@
 use browser::Browser;
-use gonk_core::{vdb::*, *};
+use gonk_core::{plays::PlayCounts, vdb::*, *};
 use gonk_player::*;
+use lyrics::Lyrics;
 use mini::defer_results;
 use playlist::{Mode as PlaylistMode, Playlist};
 use queue::Queue;
+use report::Report;
 use search::{Mode as SearchMode, Search};
 use settings::Settings;
 use std::{
@@ -14,8 +16,10 @@ use winter::*;
 
 mod browser;
 mod help;
+mod lyrics;
 mod playlist;
 mod queue;
+mod report;
 mod search;
 mod settings;
 
@@ -35,6 +39,18 @@ pub enum Mode {
     Playlist,
     Settings,
     Search,
+    Report,
+    Lyrics,
+}
+
+///Keep the queue and playlists in sync with the database, songs are matched by ID.
+fn refresh(db: &Database, songs: &mut Index<Song>, playlist: &mut Playlist) {
+    db.refresh(songs);
+    for list in playlist.lists.iter_mut() {
+        if db.refresh(&mut list.songs) {
+            list.save().unwrap();
+        }
+    }
 }
 
 fn draw(
@@ -45,9 +61,12 @@ fn draw(
     queue: &mut Queue,
     playlist: &mut Playlist,
     search: &mut Search,
+    report: &Report,
+    lyrics: &mut Lyrics,
     cursor: &mut Option<(u16, u16)>,
     songs: &mut Index<Song>,
     db: &Database,
+    plays: &PlayCounts,
     mouse: Option<(u16, u16)>,
     help: bool,
     mute: bool,
@@ -75,7 +94,9 @@ fn draw(
         Mode::Settings => settings::draw(settings, area, buf),
         Mode::Queue => queue::draw(queue, area, buf, mouse, songs, mute),
         Mode::Playlist => *cursor = playlist::draw(playlist, area, buf, mouse),
-        Mode::Search => *cursor = search::draw(search, area, buf, mouse, db),
+        Mode::Search => *cursor = search::draw(search, area, buf, mouse, db, plays),
+        Mode::Report => report::draw(report, area, buf),
+        Mode::Lyrics => lyrics::draw(lyrics, area, buf, mouse, songs, db),
     }
 
     if help {
@@ -93,11 +114,23 @@ fn draw(
     }
 }
 
-fn path(mut path: String) -> Option<std::path::PathBuf> {
-    if path.contains("~") {
-        path = path.replace("~", &user_profile_directory().unwrap());
+fn path(path: String) -> Option<std::path::PathBuf> {
+    fs::canonicalize(expand_home(&path).as_ref()).ok()
+}
+
+fn scan_summary(summary: db::ScanSummary) -> String {
+    let db::ScanSummary {
+        added,
+        updated,
+        removed,
+        ..
+    } = summary;
+
+    if added == 0 && updated == 0 && removed == 0 {
+        return String::from("No changes found");
     }
-    fs::canonicalize(path).ok()
+
+    format!("Added {added}, updated {updated} and removed {removed} files")
 }
 
 fn main() {
@@ -116,13 +149,116 @@ fn main() {
 
                 match path(args[1].clone()) {
                     Some(path) if path.exists() => {
-                        persist.music_folder = path.to_string_lossy().to_string();
-                        scan_handle = Some(db::create(&persist.music_folder));
+                        if !persist.music_folders.contains(&path) {
+                            persist.music_folders.push(path);
+                        }
+                        scan_handle = Some(db::create(&persist.music_folders));
                         scan_timer = Instant::now();
                     }
                     _ => return println!("Invalid path."),
                 }
             }
+            "remove" => {
+                if args.len() == 1 {
+                    return println!("Usage: gonk remove <path>");
+                }
+
+                //Folders that no longer exist can't be canonicalized.
+                let folder = path(args[1].clone())
+                    .filter(|folder| persist.music_folders.contains(folder))
+                    .unwrap_or_else(|| std::path::PathBuf::from(&args[1]));
+
+                let Some(i) = persist.music_folders.iter().position(|f| *f == folder) else {
+                    return println!("{} is not a music folder.", folder.display());
+                };
+
+                persist.music_folders.remove(i);
+                persist.save().unwrap();
+
+                return match db::remove_folder(&folder, &persist.music_folders) {
+                    Ok(len) => println!("Removed {} and {len} songs.", folder.display()),
+                    Err(e) => println!("Failed to remove songs! {e}"),
+                };
+            }
+            "folders" => {
+                if persist.music_folders.is_empty() {
+                    return println!("No music folders. Add one with 'gonk add <path>'.");
+                }
+                for folder in &persist.music_folders {
+                    println!("{}", folder.display());
+                }
+                return;
+            }
+            "various" => {
+                if args.len() == 1 {
+                    return println!(
+                        "Compilations are listed under '{}'.",
+                        persist.various_artists
+                    );
+                }
+
+                persist.various_artists = args[1..].join(" ");
+                persist.save().unwrap();
+                return println!(
+                    "Compilations are listed under '{}'.",
+                    persist.various_artists
+                );
+            }
+            "articles" => {
+                match args.get(1).map(String::as_str) {
+                    Some("on") => persist.ignore_articles = true,
+                    Some("off") => persist.ignore_articles = false,
+                    _ => return println!("Usage: gonk articles <on|off>"),
+                }
+                persist.save().unwrap();
+                return println!(
+                    "Leading articles are {} when sorting.",
+                    if persist.ignore_articles {
+                        "ignored"
+                    } else {
+                        "kept"
+                    }
+                );
+            }
+            "transliterate" => {
+                match args.get(1).map(String::as_str) {
+                    Some("on") => persist.transliterate = true,
+                    Some("off") => persist.transliterate = false,
+                    _ => return println!("Usage: gonk transliterate <on|off>"),
+                }
+                persist.save().unwrap();
+                return println!(
+                    "Cyrillic and Greek names {} be searched with Latin letters.",
+                    if persist.transliterate {
+                        "can"
+                    } else {
+                        "can't"
+                    }
+                );
+            }
+            "separators" => {
+                if args.get(1).is_some_and(|arg| arg == "none") {
+                    persist.separators.clear();
+                    persist.save().unwrap();
+                } else if args.len() > 1 {
+                    persist.separators = args[1..]
+                        .iter()
+                        .filter(|arg| !arg.is_empty())
+                        .cloned()
+                        .collect();
+                    persist.save().unwrap();
+                }
+                return if persist.separators.is_empty() {
+                    println!("Tags are not split.")
+                } else {
+                    let separators: Vec<String> = persist
+                        .separators
+                        .iter()
+                        .map(|separator| format!("\"{separator}\""))
+                        .collect();
+                    println!("Tags are split on: {}", separators.join(" "))
+                };
+            }
             "reset" => {
                 return match gonk_core::db::reset() {
                     Ok(_) => println!("Database reset!"),
@@ -134,7 +270,13 @@ fn main() {
                 println!("   gonk [<command> <args>]");
                 println!();
                 println!("Options");
-                println!("   add    <path> Add music to the library");
+                println!("   add    <path> Add a music folder to the library");
+                println!("   remove <path> Remove a music folder and its songs");
+                println!("   folders       List the music folders");
+                println!("   various <name> Set the artist compilations are listed under");
+                println!("   articles <on|off> Ignore leading articles when sorting");
+                println!("   separators <separator>... Split artists, genres and composers");
+                println!("   transliterate <on|off> Search Cyrillic and Greek with Latin letters");
                 println!("   reset         Reset the database");
                 println!("   buffer <size> Set a custom ring buffer size");
                 return;
@@ -189,13 +331,23 @@ fn main() {
         seek(persist.elapsed);
     }
 
-    let mut db = Database::new();
+    let mut db = Database::new(&persist);
     let mut browser = Browser::new(&db);
 
+    if !db.errors.is_empty() {
+        let len = db.errors.len();
+        let s = if len == 1 { "" } else { "s" };
+        log!("{len} song{s} in the database could not be read. See %appdata%/gonk/gonk.log for details.");
+        fs::write(gonk_path().join("gonk.log"), db.errors.join("\n")).unwrap();
+    }
+
     //Everything here initialises quickly.
     let mut queue = Queue::new(index.unwrap_or(0));
     let mut playlist = Playlist::new().unwrap();
     let mut search = Search::new();
+    let mut report = Report::new();
+    let mut lyrics = Lyrics::new();
+    let mut plays = PlayCounts::load();
     let mut mode = Mode::Browser;
     let mut last_tick = Instant::now();
     let mut ft = Instant::now();
@@ -208,6 +360,18 @@ fn main() {
     let mut shift;
     let mut control;
 
+    //Changes are held until any scan has finished.
+    let watcher = match watcher::watch(&persist.music_folders) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            log!("Not watching the music folders for changes: {err}");
+            None
+        }
+    };
+    let mut changes: Vec<watcher::Event> = Vec::new();
+    //Changes are applied on another thread, one batch at a time.
+    let mut apply_handle: Option<std::thread::JoinHandle<Result<db::Changes, String>>> = None;
+
     let mut settings = thread.join().unwrap();
 
     //If there are songs in the queue and the database isn't scanning, display the queue.
@@ -224,6 +388,8 @@ fn main() {
                 Mode::Playlist => playlist::up(&mut playlist, amount),
                 Mode::Settings => settings::up(&mut settings, amount),
                 Mode::Search => search.results.up_n(amount),
+                Mode::Report => report::up(&mut report, amount),
+                Mode::Lyrics => lyrics::up(&mut lyrics, amount),
             }
         }};
     }
@@ -237,6 +403,8 @@ fn main() {
                 Mode::Playlist => playlist::down(&mut playlist, amount),
                 Mode::Settings => settings::down(&mut settings, amount),
                 Mode::Search => search.results.down_n(amount),
+                Mode::Report => report::down(&mut report, amount),
+                Mode::Lyrics => lyrics::down(&mut lyrics, amount),
             }
         }};
     }
@@ -267,30 +435,28 @@ fn main() {
                 let handle = scan_handle.take().unwrap();
                 let result = handle.join().unwrap();
 
-                db = Database::new();
+                db = Database::new(&persist);
+                refresh(&db, &mut songs, &mut playlist);
                 log::clear();
 
                 match result {
-                    db::ScanResult::Completed => {
+                    db::ScanResult::Completed(summary) => {
                         log!(
-                            "Finished adding {} files in {:.2} seconds.",
-                            db.len,
+                            "{} in {:.2} seconds.",
+                            scan_summary(summary),
                             scan_timer.elapsed().as_secs_f32()
                         );
+                        report::set(&mut report, Vec::new());
                     }
-                    db::ScanResult::CompletedWithErrors(errors) => {
-                        let dir = "See %appdata%/gonk/gonk.log for details.";
+                    db::ScanResult::CompletedWithErrors(summary, errors) => {
                         let len = errors.len();
                         let s = if len == 1 { "" } else { "s" };
 
                         log!(
-                            "Added {} files with {len} error{s}. {dir}",
-                            db.len.saturating_sub(len)
+                            "{} with {len} problem{s}. Press 5 to see the scan report.",
+                            scan_summary(summary)
                         );
-
-                        let path = gonk_path().join("gonk.log");
-                        let errors = errors.join("\n");
-                        fs::write(path, errors).unwrap();
+                        report::set(&mut report, errors);
                     }
                     db::ScanResult::FileInUse => {
                         log!("Could not update database, file in use.")
@@ -298,13 +464,55 @@ fn main() {
                 }
 
                 browser::refresh(&mut browser, &db);
-                search.results = Index::new(db.search(&search.query), None);
+                search::update(&mut search, &db, &plays);
 
                 //No need to reset scan_timer since it's reset with new scans.
                 scan_handle = None;
             }
         }
 
+        if let Some(watcher) = &watcher {
+            while let Ok(events) = watcher.try_recv() {
+                for event in events {
+                    watcher::push(&mut changes, event);
+                }
+            }
+        }
+
+        if !changes.is_empty() && scan_handle.is_none() && apply_handle.is_none() {
+            let events = std::mem::take(&mut changes);
+            let folders = persist.music_folders.clone();
+            apply_handle = Some(std::thread::spawn(move || {
+                db::apply(&events, &folders).map_err(|err| err.to_string())
+            }));
+        }
+
+        if let Some(handle) = &apply_handle {
+            if handle.is_finished() {
+                let handle = apply_handle.take().unwrap();
+                match handle.join().unwrap() {
+                    Ok(result) => {
+                        db = Database::new(&persist);
+                        refresh(&db, &mut songs, &mut playlist);
+                        browser::reload(&mut browser, &db);
+                        search::update(&mut search, &db, &plays);
+
+                        if result.summary != db::ScanSummary::default() {
+                            log!("{}.", scan_summary(result.summary));
+                        }
+
+                        if !result.errors.is_empty() {
+                            let len = result.errors.len();
+                            let s = if len == 1 { "" } else { "s" };
+                            log!("{len} file{s} had problems. Press 5 to see the scan report.");
+                            report::set(&mut report, result.errors);
+                        }
+                    }
+                    Err(err) => log!("Could not update the database: {err}"),
+                }
+            }
+        }
+
         if last_tick.elapsed() >= Duration::from_millis(150) {
             if scan_handle.is_some() {
                 if dots < 3 {
@@ -312,10 +520,15 @@ fn main() {
                 } else {
                     dots = 1;
                 }
+                let folders: Vec<_> = persist
+                    .music_folders
+                    .iter()
+                    .map(|folder| folder.to_string_lossy())
+                    .collect();
                 log!(
                     "Scanning {} for files{}",
                     //Remove the UNC \\?\ from the path.
-                    &persist.music_folder.replace("\\\\?\\", ""),
+                    &folders.join(", ").replace("\\\\?\\", ""),
                     ".".repeat(dots)
                 );
             }
@@ -339,6 +552,11 @@ fn main() {
 
         //Play the next song if the current is finished.
         if gonk_player::play_next() && !songs.is_empty() {
+            if let Some(song) = songs.selected() {
+                plays.played(song.id);
+                db.played(song.id);
+                let _ = plays.save();
+            }
             songs.down();
             if let Some(song) = songs.selected() {
                 play_song(song);
@@ -356,9 +574,12 @@ fn main() {
             &mut queue,
             &mut playlist,
             &mut search,
+            &report,
+            &mut lyrics,
             &mut cursor,
             &mut songs,
             &db,
+            &plays,
             None,
             help,
             mute,
@@ -382,9 +603,12 @@ fn main() {
                         &mut queue,
                         &mut playlist,
                         &mut search,
+                        &report,
+                        &mut lyrics,
                         &mut cursor,
                         &mut songs,
                         &db,
+                        &plays,
                         Some((x, y)),
                         help,
                         mute,
@@ -479,11 +703,11 @@ fn main() {
                 //Force delete -> Shift + X.
                 Event::Char('X') if mode == Mode::Playlist => playlist::delete(&mut playlist, true),
                 Event::Char('u') if mode == Mode::Browser || mode == Mode::Playlist => {
-                    if scan_handle.is_none() {
-                        if persist.music_folder.is_empty() {
+                    if scan_handle.is_none() && apply_handle.is_none() {
+                        if persist.music_folders.is_empty() {
                             gonk_core::log!("Nothing to scan! Add a folder with 'gonk add /path/'");
                         } else {
-                            scan_handle = Some(db::create(&persist.music_folder));
+                            scan_handle = Some(db::create(&persist.music_folders));
                             scan_timer = Instant::now();
                             playlist.lists = Index::from(gonk_core::playlist::playlists());
                         }
@@ -593,6 +817,8 @@ fn main() {
                 Event::Char('2') => mode = Mode::Browser,
                 Event::Char('3') => mode = Mode::Playlist,
                 Event::Char('4') => mode = Mode::Settings,
+                Event::Char('5') => mode = Mode::Report,
+                Event::Char('6') => mode = Mode::Lyrics,
                 Event::Function(1) => queue::constraint(&mut queue, 0, shift),
                 Event::Function(2) => queue::constraint(&mut queue, 1, shift),
                 Event::Function(3) => queue::constraint(&mut queue, 2, shift),
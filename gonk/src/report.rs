@@ -0,0 +1,74 @@
+use gonk_core::{db, db::ScanError, gonk_path};
+use std::{fs, path::PathBuf};
+use winter::*;
+
+///Files from the last scan that couldn't be read or had broken tags.
+pub struct Report {
+    pub errors: Vec<(PathBuf, ScanError)>,
+    pub index: Option<usize>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self {
+            errors: Vec::new(),
+            index: None,
+        }
+    }
+}
+
+///Replace the report, the errors are also written to `gonk.log`.
+pub fn set(report: &mut Report, errors: Vec<(PathBuf, ScanError)>) {
+    if !errors.is_empty() {
+        let _ = fs::write(gonk_path().join("gonk.log"), db::scan_log(&errors));
+    }
+    report.index = if errors.is_empty() { None } else { Some(0) };
+    report.errors = errors;
+}
+
+pub fn up(report: &mut Report, amount: usize) {
+    let Some(index) = report.index else { return };
+    report.index = Some(gonk_core::up(report.errors.len(), index, amount));
+}
+
+pub fn down(report: &mut Report, amount: usize) {
+    let Some(index) = report.index else { return };
+    report.index = Some(gonk_core::down(report.errors.len(), index, amount));
+}
+
+pub fn draw(report: &Report, area: winter::Rect, buf: &mut winter::Buffer) {
+    let block = block().title("Scan Report".bold()).title_margin(1);
+
+    if report.errors.is_empty() {
+        return lines!("No problems found in the last scan.")
+            .block(block)
+            .draw(area, buf);
+    }
+
+    let rows: Vec<Row> = report
+        .errors
+        .iter()
+        .enumerate()
+        .map(|(i, (path, error))| {
+            let selected = if report.index == Some(i) { ">" } else { "" };
+            //Warnings were still added to the database.
+            let color = if error.is_warning() { Yellow } else { Red };
+            row![
+                selected,
+                error.kind().fg(color),
+                text!("{}", path.display()),
+                text!("{}", error.details()).dim()
+            ]
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(2),
+        Constraint::Length(20),
+        Constraint::Percentage(50),
+        Constraint::Percentage(50),
+    ];
+    let header = header![text!(), "Kind".bold(), "File".bold(), "Details".bold()];
+    let table = table(rows, &widths).header(header).block(block).spacing(1);
+    table.draw(area, buf, report.index);
+}
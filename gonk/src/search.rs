@@ -1,8 +1,12 @@
 use crate::{ALBUM, ARTIST, TITLE};
 use gonk_core::{
+    plays::PlayCounts,
+    query::ParseError,
+    search::SearchResult,
     vdb::{Database, Item},
     Index, Song,
 };
+use std::ops::Range;
 use winter::*;
 
 #[derive(PartialEq, Eq, Debug)]
@@ -15,7 +19,9 @@ pub struct Search {
     pub query: String,
     pub query_changed: bool,
     pub mode: Mode,
-    pub results: Index<Item>,
+    pub results: Index<SearchResult>,
+    ///Why the query couldn't be parsed.
+    pub error: Option<ParseError>,
 }
 
 impl Search {
@@ -25,10 +31,22 @@ impl Search {
             query_changed: false,
             mode: Mode::Search,
             results: Index::default(),
+            error: None,
         }
     }
 }
 
+///Run the query again, results are kept when it can't be parsed.
+pub fn update(search: &mut Search, db: &Database, plays: &PlayCounts) {
+    match db.search(&search.query, plays) {
+        Ok(results) => {
+            search.results = Index::new(results, None);
+            search.error = None;
+        }
+        Err(error) => search.error = Some(error),
+    }
+}
+
 //TODO: Artist and albums colors aren't quite right.
 pub fn draw(
     search: &mut Search,
@@ -36,10 +54,11 @@ pub fn draw(
     buf: &mut winter::Buffer,
     mouse: Option<(u16, u16)>,
     db: &Database,
+    plays: &PlayCounts,
 ) -> Option<(u16, u16)> {
     if search.query_changed {
         search.query_changed = !search.query_changed;
-        *search.results = db.search(&search.query);
+        update(search, db, plays);
     }
 
     let v = layout(area, Vertical, &[Length(3), Fill]);
@@ -59,10 +78,27 @@ pub fn draw(
         }
     }
 
-    lines!(search.query.as_str())
-        .block(block().title("Search:"))
-        .scroll()
-        .draw(v[0], buf);
+    //Show where the query is malformed.
+    match &search.error {
+        Some(error) => {
+            let query = search.query.as_str();
+            let range = error.range.clone();
+            lines!(
+                &query[..range.start],
+                query[range.clone()].fg(Red).bold(),
+                &query[range.end..]
+            )
+            .block(block().title(error.message.as_str().fg(Red)))
+            .scroll()
+            .draw(v[0], buf);
+        }
+        None => {
+            lines!(search.query.as_str())
+                .block(block().title("Search:"))
+                .scroll()
+                .draw(v[0], buf);
+        }
+    }
 
     let rows: Vec<Row> = search
         .results
@@ -80,22 +116,35 @@ pub fn draw(
         })
         .collect();
 
-    let table = table(
-        rows,
-        &[
+    //Lyrics and comment searches get a column for the line that matched.
+    let snippets = search.results.iter().any(|result| result.snippet.is_some());
+    let widths = if snippets {
+        [
+            Constraint::Length(1),
+            Constraint::Percentage(30),
+            Constraint::Percentage(20),
+            Constraint::Percentage(15),
+            Constraint::Percentage(35),
+        ]
+    } else {
+        [
             Constraint::Length(1),
             Constraint::Percentage(50),
             Constraint::Percentage(30),
             Constraint::Percentage(20),
-        ],
-    )
-    .header(header![
-        text!(),
-        "Name".italic(),
-        "Album".italic(),
-        "Artist".italic()
-    ])
-    .block(block());
+            Constraint::Length(0),
+        ]
+    };
+
+    let table = table(rows, &widths)
+        .header(header![
+            text!(),
+            "Name".italic(),
+            "Album".italic(),
+            "Artist".italic(),
+            if snippets { "Lyrics".italic() } else { text!() }
+        ])
+        .block(block());
 
     table.draw(v[1], buf, search.results.index());
 
@@ -120,31 +169,99 @@ pub fn draw(
     }
 }
 
+///How many matched parts of a name are highlighted separately.
+const HIGHLIGHTS: usize = 3;
+
+///Split a name into unmatched and matched parts, starting and ending with an unmatched part.
+///Matches past the last highlight are joined into it.
+fn split<'a>(name: &'a str, highlights: &[Range<usize>]) -> [&'a str; 2 * HIGHLIGHTS + 1] {
+    let mut parts = [""; 2 * HIGHLIGHTS + 1];
+    let mut end = 0;
+    for (i, range) in highlights.iter().take(HIGHLIGHTS).enumerate() {
+        let range = if i + 1 == HIGHLIGHTS {
+            range.start..highlights.last().unwrap().end
+        } else {
+            range.clone()
+        };
+        parts[2 * i] = &name[end..range.start];
+        parts[2 * i + 1] = &name[range.clone()];
+        end = range.end;
+    }
+    parts[2 * HIGHLIGHTS] = &name[end..];
+    parts
+}
+
 //Items have a lifetime of 'search because they live in the Search struct.
-fn cell(item: &Item, selected: bool) -> Row<'_> {
+fn cell(result: &SearchResult, selected: bool) -> Row<'_> {
     let selected_cell = if selected { ">" } else { "" };
+    let [a, b, c, d, e, f, g] = split(result.item.name(), &result.highlights);
 
-    match item {
-        Item::Song((artist, album, name, _, _)) => row![
-            selected_cell,
-            name.as_str().fg(TITLE),
-            album.as_str().fg(ALBUM),
-            artist.as_str().fg(ARTIST)
-        ],
-        Item::Album((artist, album)) => row![
+    match &result.item {
+        Item::Song((artist, album, _, _, _, _)) => {
+            let snippet = match &result.snippet {
+                Some(snippet) => {
+                    let [a, b, c, d, e, f, g] = split(&snippet.text, &snippet.highlights);
+                    lines!(
+                        a.dim(),
+                        b.bold(),
+                        c.dim(),
+                        d.bold(),
+                        e.dim(),
+                        f.bold(),
+                        g.dim()
+                    )
+                }
+                None => lines!(""),
+            };
+            row![
+                selected_cell,
+                lines!(
+                    a.fg(TITLE),
+                    b.fg(TITLE).bold(),
+                    c.fg(TITLE),
+                    d.fg(TITLE).bold(),
+                    e.fg(TITLE),
+                    f.fg(TITLE).bold(),
+                    g.fg(TITLE)
+                ),
+                album.as_str().fg(ALBUM),
+                artist.as_str().fg(ARTIST),
+                snippet
+            ]
+        }
+        Item::Album((artist, _, _)) => row![
             selected_cell,
-            lines!(text!("{album} - ").fg(ALBUM), "Album".fg(ALBUM).italic()),
+            lines!(
+                a.fg(ALBUM),
+                b.fg(ALBUM).bold(),
+                c.fg(ALBUM),
+                d.fg(ALBUM).bold(),
+                e.fg(ALBUM),
+                f.fg(ALBUM).bold(),
+                g.fg(ALBUM),
+                " - ".fg(ALBUM),
+                "Album".fg(ALBUM).italic()
+            ),
             "-",
-            artist.fg(ARTIST)
+            artist.fg(ARTIST),
+            ""
         ],
-        Item::Artist(artist) => row![
+        Item::Artist(_) => row![
             selected_cell,
             lines!(
-                text!("{artist} - ").fg(ARTIST),
+                a.fg(ARTIST),
+                b.fg(ARTIST).bold(),
+                c.fg(ARTIST),
+                d.fg(ARTIST).bold(),
+                e.fg(ARTIST),
+                f.fg(ARTIST).bold(),
+                g.fg(ARTIST),
+                " - ".fg(ARTIST),
                 "Artist".fg(ARTIST).italic()
             ),
             "-",
-            "-"
+            "-",
+            ""
         ],
     }
 }
@@ -185,13 +302,16 @@ pub fn on_enter(search: &mut Search, db: &Database) -> Option<Vec<Song>> {
             }
             None
         }
-        Mode::Select => search.results.selected().map(|item| match item {
-            Item::Song((artist, album, _, disc, number)) => {
-                vec![db.song(artist, album, *disc, *number).clone()]
-            }
-            Item::Album((artist, album)) => db.album(artist, album).songs.clone(),
+        Mode::Select => search.results.selected().map(|result| match &result.item {
+            //The database may have changed since the search.
+            Item::Song((_, _, _, _, _, id)) => db.song(*id).cloned().into_iter().collect(),
+            Item::Album((artist, album, folder)) => db
+                .album(artist, album, folder.as_deref())
+                .map(|album| album.songs.clone())
+                .unwrap_or_default(),
             Item::Artist(artist) => db
                 .albums_by_artist(artist)
+                .unwrap_or_default()
                 .iter()
                 .flat_map(|album| album.songs.clone())
                 .collect(),
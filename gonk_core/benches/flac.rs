@@ -1,21 +1,21 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use gonk_core::{read_metadata, read_metadata_old, Song};
-use winwalk::DirEntry;
+use gonk_core::{read_metadata, read_metadata_old, walk, Song};
+use std::path::PathBuf;
 
-fn custom(files: &[DirEntry]) -> Vec<Result<Song, String>> {
+fn custom(files: &[PathBuf]) -> Vec<Result<Song, String>> {
     files
         .iter()
-        .map(|file| match read_metadata(&file.path) {
+        .map(|file| match read_metadata(file) {
             Ok(song) => Ok(song),
-            Err(err) => Err(format!("Error: ({err}) @ {}", file.path)),
+            Err(err) => Err(format!("Error: ({err}) @ {}", file.display())),
         })
         .collect()
 }
 
-fn custom_old(files: &[DirEntry]) -> Vec<Result<Song, String>> {
+fn custom_old(files: &[PathBuf]) -> Vec<Result<Song, String>> {
     files
         .iter()
-        .map(|file| match read_metadata_old(&file.path) {
+        .map(|file| match read_metadata_old(file) {
             Ok(metadata) => {
                 let track_number = metadata
                     .get("TRACKNUMBER")
@@ -61,16 +61,17 @@ fn custom_old(files: &[DirEntry]) -> Vec<Result<Song, String>> {
                     artist: artist.to_string(),
                     disc_number,
                     track_number,
-                    path: file.path.clone(),
+                    path: file.clone(),
                     gain,
+                    ..Song::default()
                 })
             }
-            Err(err) => Err(format!("Error: ({err}) @ {}", file.path)),
+            Err(err) => Err(format!("Error: ({err}) @ {}", file.display())),
         })
         .collect()
 }
 
-fn symphonia(files: &[DirEntry]) -> Vec<Result<Song, String>> {
+fn symphonia(files: &[PathBuf]) -> Vec<Result<Song, String>> {
     use std::fs::File;
     use symphonia::{
         core::{formats::FormatOptions, io::*, meta::*, probe::Hint},
@@ -79,9 +80,9 @@ fn symphonia(files: &[DirEntry]) -> Vec<Result<Song, String>> {
     files
         .iter()
         .map(|entry| {
-            let file = match File::open(&entry.path) {
+            let file = match File::open(entry) {
                 Ok(file) => file,
-                Err(err) => return Err(format!("Error: ({err}) @ {}", entry.path)),
+                Err(err) => return Err(format!("Error: ({err}) @ {}", entry.display())),
             };
 
             let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
@@ -96,7 +97,7 @@ fn symphonia(files: &[DirEntry]) -> Vec<Result<Song, String>> {
                 },
             ) {
                 Ok(probe) => probe,
-                Err(err) => return Err(format!("Error: ({err}) @ {}", entry.path))?,
+                Err(err) => return Err(format!("Error: ({err}) @ {}", entry.display()))?,
             };
 
             let mut title = String::from("Unknown Title");
@@ -165,8 +166,9 @@ fn symphonia(files: &[DirEntry]) -> Vec<Result<Song, String>> {
                 artist,
                 disc_number,
                 track_number,
-                path: entry.path.clone(),
+                path: entry.clone(),
                 gain,
+                ..Song::default()
             })
         })
         .collect()
@@ -178,16 +180,7 @@ fn flac(c: &mut Criterion) {
     let mut group = c.benchmark_group("flac");
     group.sample_size(10);
 
-    let paths: Vec<winwalk::DirEntry> = winwalk::walkdir(PATH, 0)
-        .into_iter()
-        .flatten()
-        .filter(|entry| match entry.extension() {
-            Some(ex) => {
-                matches!(ex.to_str(), Some("flac"))
-            }
-            None => false,
-        })
-        .collect();
+    let paths = walk::walk(PATH, &["flac"]);
 
     group.bench_function("custom new", |b| {
         b.iter(|| {
@@ -0,0 +1,102 @@
+//! Sort order for artist and album names.
+//!
+//! Names are compared case-insensitively with accents removed, so "Émilie" sorts with "Emilie".
+//! Characters are folded the same way as in search, see `search::fold`.
+//! Leading articles can be ignored so "The Beatles" is filed under B.
+use crate::search;
+use std::cmp::Ordering;
+
+///Articles ignored at the start of names, matched case-insensitively.
+pub const ARTICLES: [&str; 16] = [
+    "the", "a", "an", "die", "der", "das", "le", "la", "les", "l'", "el", "los", "las", "il", "lo",
+    "gli",
+];
+
+///Remove a leading article, names that are only an article are left alone.
+pub fn strip_article(name: &str) -> &str {
+    let name = name.trim_start();
+
+    for article in ARTICLES {
+        let Some(prefix) = name.get(..article.len()) else {
+            continue;
+        };
+        if !prefix.eq_ignore_ascii_case(article) {
+            continue;
+        }
+
+        let rest = &name[article.len()..];
+        //"L'amour" has no space after the article.
+        let rest = if article.ends_with('\'') {
+            rest
+        } else {
+            match rest.strip_prefix(' ') {
+                Some(rest) => rest,
+                None => continue,
+            }
+        };
+
+        if !rest.trim().is_empty() {
+            return rest.trim_start();
+        }
+    }
+
+    name
+}
+
+///The string names are compared by.
+pub fn sort_key(name: &str, ignore_articles: bool) -> String {
+    let name = if ignore_articles {
+        strip_article(name)
+    } else {
+        name
+    };
+
+    let mut key = String::with_capacity(name.len());
+    for c in name.chars() {
+        search::fold(c, |c| key.push(c));
+    }
+    key
+}
+
+///Compare two names, falling back to the original names so the order is stable.
+pub fn compare(a: &str, b: &str, ignore_articles: bool) -> Ordering {
+    sort_key(a, ignore_articles)
+        .cmp(&sort_key(b, ignore_articles))
+        .then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn articles() {
+        assert_eq!(strip_article("The Beatles"), "Beatles");
+        assert_eq!(strip_article("the beatles"), "beatles");
+        assert_eq!(strip_article("Die Ärzte"), "Ärzte");
+        assert_eq!(strip_article("L'Impératrice"), "Impératrice");
+        assert_eq!(strip_article("The The"), "The");
+        assert_eq!(strip_article("The"), "The");
+        assert_eq!(strip_article("Theory"), "Theory");
+        assert_eq!(strip_article("Abba"), "Abba");
+    }
+
+    #[test]
+    fn order() {
+        let mut names = ["Zebra", "The Beatles", "Émilie", "abba", "Eagles", "Öl"];
+        names.sort_by(|a, b| compare(a, b, true));
+        assert_eq!(
+            names,
+            ["abba", "The Beatles", "Eagles", "Émilie", "Öl", "Zebra"]
+        );
+
+        names.sort_by(|a, b| compare(a, b, false));
+        assert_eq!(
+            names,
+            ["abba", "Eagles", "Émilie", "Öl", "The Beatles", "Zebra"]
+        );
+
+        assert_eq!(sort_key("Ærøskøbing", false), "aeroskobing");
+        assert_eq!(sort_key("Ｒｅｍｉｘ", false), "remix");
+    }
+}
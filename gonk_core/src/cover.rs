@@ -0,0 +1,330 @@
+//! Album covers
+//!
+//! Embedded covers are read from FLAC PICTURE blocks, ID3 APIC frames and
+//! METADATA_BLOCK_PICTURE comments in Ogg files.
+//! Albums without one fall back to an image next to the songs like `cover.jpg`.
+//!
+//! With the `covers` feature, covers are resized and cached in `gonk_path()/covers`, one file per album.
+#[cfg(feature = "covers")]
+use crate::{db::modified, gonk_path, log, vdb::album_folder};
+use crate::{read_flac_pictures, read_id3_pictures, read_ogg_pictures, Song};
+#[cfg(feature = "covers")]
+use image::{imageops::FilterType, ImageFormat, RgbImage};
+#[cfg(feature = "covers")]
+use std::time::Duration;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+///Cached covers fit inside a square this many pixels wide.
+#[cfg(feature = "covers")]
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+///Albums without a cover are searched again after this long.
+#[cfg(feature = "covers")]
+const RETRY_MISSING: Duration = Duration::from_secs(24 * 60 * 60);
+
+///The picture type of a front cover.
+pub const FRONT_COVER: u32 = 3;
+
+///Checked in order when none of the songs have an embedded cover, case is ignored.
+pub const FOLDER_IMAGES: [&str; 8] = [
+    "cover.jpg",
+    "cover.jpeg",
+    "cover.png",
+    "folder.jpg",
+    "folder.jpeg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    ///Front cover, back cover, artist, etc. Uses the ID3v2 APIC picture types.
+    pub kind: u32,
+    pub mime: String,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    ///Parse a FLAC PICTURE block, METADATA_BLOCK_PICTURE uses the same layout.
+    ///
+    ///https://xiph.org/flac/format.html#metadata_block_picture
+    pub fn flac(block: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut i = 0;
+        let kind = u32_be(block, &mut i)?;
+        let mime = bytes(block, &mut i)?;
+        let description = bytes(block, &mut i)?;
+        //Width, height, color depth and the number of indexed colors.
+        i += 16;
+        let data = bytes(block, &mut i)?;
+
+        Ok(Self {
+            kind: kind as u32,
+            mime: String::from_utf8_lossy(mime).to_string(),
+            description: String::from_utf8_lossy(description).to_string(),
+            data: data.to_vec(),
+        })
+    }
+}
+
+fn u32_be(bytes: &[u8], i: &mut usize) -> Result<usize, Box<dyn Error>> {
+    let slice = bytes.get(*i..*i + 4).ok_or("Truncated picture block.")?;
+    *i += 4;
+    Ok(u32::from_be_bytes(slice.try_into()?) as usize)
+}
+
+///A 32-bit length followed by that many bytes.
+fn bytes<'a>(bytes: &'a [u8], i: &mut usize) -> Result<&'a [u8], Box<dyn Error>> {
+    let length = u32_be(bytes, i)?;
+    let slice = bytes
+        .get(*i..*i + length)
+        .ok_or("Truncated picture block.")?;
+    *i += length;
+    Ok(slice)
+}
+
+///Decode standard base64, padding is optional and whitespace is ignored.
+pub fn base64(s: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(s.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in s.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b if b.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+///The front cover if there is one, otherwise the first picture.
+fn front(pictures: Vec<Picture>) -> Option<Picture> {
+    let i = pictures
+        .iter()
+        .position(|picture| picture.kind == FRONT_COVER)
+        .unwrap_or(0);
+    pictures.into_iter().nth(i)
+}
+
+///The cover embedded in a song.
+pub fn embedded(path: &Path) -> Option<Picture> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let pictures = match extension.as_str() {
+        "flac" => read_flac_pictures(path),
+        "mp3" => read_id3_pictures(path),
+        "ogg" => read_ogg_pictures(path),
+        _ => return None,
+    };
+    front(pictures.ok()?)
+}
+
+///The first image in `FOLDER_IMAGES` that's inside `folder`.
+pub fn folder(folder: &Path) -> Option<PathBuf> {
+    let files: Vec<PathBuf> = fs::read_dir(folder)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+
+    FOLDER_IMAGES.iter().find_map(|name| {
+        files
+            .iter()
+            .find(|file| {
+                file.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+    })
+}
+
+///The full size cover of an album, embedded covers are preferred over folder images.
+pub fn find(songs: &[Song]) -> Option<Vec<u8>> {
+    if let Some(picture) = songs.iter().find_map(|song| embedded(&song.path)) {
+        return Some(picture.data);
+    }
+
+    let mut folders: Vec<&Path> = songs.iter().filter_map(|song| song.path.parent()).collect();
+    folders.dedup();
+    folders
+        .into_iter()
+        .find_map(folder)
+        .and_then(|path| fs::read(path).ok())
+}
+
+///Decode an image and shrink it to fit in `THUMBNAIL_SIZE`.
+#[cfg(feature = "covers")]
+pub fn thumbnail(data: &[u8]) -> Result<RgbImage, Box<dyn Error>> {
+    let image = image::load_from_memory(data)?;
+    let image = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+    } else {
+        image
+    };
+    Ok(image.to_rgb8())
+}
+
+///Where the cover of an album is cached.
+///
+///Albums with the same name in different folders are kept apart by the folder.
+#[cfg(feature = "covers")]
+pub fn cache_path(artist: &str, album: &str, folder: Option<&Path>) -> PathBuf {
+    //64-bit FNV-1a, the nulls keep ("ab", "c") and ("a", "bc") apart.
+    let folder = folder.map(crate::db::encode_path).unwrap_or_default();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in artist
+        .bytes()
+        .chain([0])
+        .chain(album.bytes())
+        .chain([0])
+        .chain(folder.bytes())
+    {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    gonk_path().join("covers").join(format!("{hash:016x}.jpg"))
+}
+
+///When the songs, their folders or the folder images last changed.
+///
+///Adding an image changes the folder, replacing one only changes the image.
+#[cfg(feature = "covers")]
+fn last_changed(songs: &[Song]) -> u64 {
+    let mut folders: Vec<&Path> = songs.iter().filter_map(|song| song.path.parent()).collect();
+    folders.dedup();
+    let images = folders.iter().filter_map(|path| folder(path));
+    let files = folders
+        .iter()
+        .map(|folder| folder.to_path_buf())
+        .chain(images);
+    let changed = files.filter_map(|file| fs::metadata(file).ok().map(|m| modified(&m)));
+    songs
+        .iter()
+        .map(|song| song.modified)
+        .chain(changed)
+        .max()
+        .unwrap_or(0)
+}
+
+///The cached cover of an album, created if it's missing or older than the songs or folder images.
+///
+///Albums without a cover are cached as an empty file, which is retried after `RETRY_MISSING`.
+#[cfg(feature = "covers")]
+pub fn cover(artist: &str, album: &str, songs: &[Song]) -> Option<PathBuf> {
+    let folder = songs.first().and_then(|song| album_folder(&song.path));
+    let path = cache_path(artist, album, folder);
+
+    if let Ok(metadata) = fs::metadata(&path) {
+        let found = metadata.len() != 0;
+        let recent = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.elapsed().ok())
+            .is_some_and(|age| age < RETRY_MISSING);
+        if modified(&metadata) >= last_changed(songs) && (found || recent) {
+            return found.then_some(path);
+        }
+    }
+
+    let thumbnail = find(songs).and_then(|data| thumbnail(&data).ok());
+    if let Err(err) = save(&path, thumbnail.as_ref()) {
+        log!("Failed to cache the cover of {album}: {err}");
+    }
+    thumbnail.map(|_| path)
+}
+
+#[cfg(feature = "covers")]
+fn save(path: &Path, thumbnail: Option<&RgbImage>) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(path.parent().ok_or("Invalid cover path.")?)?;
+
+    //Write to a temporary file so a partial cover is never read.
+    let temp = path.with_extension("tmp");
+    match thumbnail {
+        Some(thumbnail) => thumbnail.save_with_format(&temp, ImageFormat::Jpeg)?,
+        None => fs::write(&temp, [])?,
+    }
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(kind: u32, mime: &str, data: &[u8]) -> Vec<u8> {
+        let mut block = kind.to_be_bytes().to_vec();
+        block.extend((mime.len() as u32).to_be_bytes());
+        block.extend(mime.as_bytes());
+        block.extend(5u32.to_be_bytes());
+        block.extend(b"Cover");
+        block.extend([0; 16]);
+        block.extend((data.len() as u32).to_be_bytes());
+        block.extend(data);
+        block
+    }
+
+    #[test]
+    fn flac() {
+        let picture = Picture::flac(&block(3, "image/png", b"png")).unwrap();
+        assert_eq!(picture.kind, FRONT_COVER);
+        assert_eq!(picture.mime, "image/png");
+        assert_eq!(picture.description, "Cover");
+        assert_eq!(picture.data, b"png");
+
+        let block = block(3, "image/png", b"png");
+        assert!(Picture::flac(&block[..block.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn base64_decode() {
+        assert_eq!(base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64("aGVs\nbG8h").unwrap(), b"hello!");
+        assert_eq!(base64("").unwrap(), b"");
+        assert_eq!(base64("aGV*"), None);
+    }
+
+    #[test]
+    fn front_cover() {
+        let picture = |kind| Picture {
+            kind,
+            mime: String::new(),
+            description: String::new(),
+            data: Vec::new(),
+        };
+        assert_eq!(front(vec![picture(4), picture(3)]).unwrap().kind, 3);
+        assert_eq!(front(vec![picture(4), picture(0)]).unwrap().kind, 4);
+        assert_eq!(front(Vec::new()), None);
+    }
+
+    #[test]
+    #[cfg(feature = "covers")]
+    fn cache_key() {
+        assert_ne!(cache_path("ab", "c", None), cache_path("a", "bc", None));
+        assert_eq!(cache_path("a", "b", None), cache_path("a", "b", None));
+        let folder = |name| Some(Path::new(name));
+        assert_ne!(
+            cache_path("a", "b", folder("one")),
+            cache_path("a", "b", folder("two"))
+        );
+    }
+}
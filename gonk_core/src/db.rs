@@ -1,6 +1,7 @@
 use crate::*;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
     io::{BufWriter, Write},
     thread::{self, JoinHandle},
@@ -10,15 +11,67 @@ use std::{
 pub struct Song {
     pub title: String,
     pub album: String,
+    ///Album artist, falls back to the track artist.
     pub artist: String,
-    pub disc_number: u8,
-    pub track_number: u8,
-    pub path: String,
+    pub disc_number: u16,
+    pub track_number: u16,
+    pub path: PathBuf,
     pub gain: f32,
+    ///Zero if unknown.
+    pub year: u16,
+    pub genres: Vec<String>,
+    pub composers: Vec<String>,
+    ///The artists of this track, which may differ from the album artist.
+    pub track_artists: Vec<String>,
+    ///Duration in seconds.
+    pub duration: f32,
+    pub sample_rate: u32,
+    ///Zero for lossy formats.
+    pub bit_depth: u8,
+    ///Average bitrate in kbps.
+    pub bitrate: u32,
+    pub channels: u8,
+    ///File size in bytes.
+    pub file_size: u64,
+    ///Modification time in nanoseconds since the unix epoch, used to skip unchanged files when rescanning.
+    pub modified: u64,
+    ///The music folder this song was found in.
+    pub root: PathBuf,
+    ///Number of tracks on the disc, zero if unknown.
+    pub track_total: u16,
+    ///Number of discs in the album, zero if unknown.
+    pub disc_total: u16,
+    ///The album artist tag, empty if the song doesn't have one.
+    pub album_artist: String,
+    ///Part of a compilation album, from the COMPILATION or TCMP tag.
+    pub compilation: bool,
+    ///Sort tags, empty if the song doesn't have them.
+    pub artist_sort: String,
+    pub album_artist_sort: String,
+    pub album_sort: String,
+    ///Kept when the file is moved or its tags change.
+    pub id: SongId,
+    ///Plain or LRC lyrics, from a `.lrc` or `.txt` file next to the song or the tags.
+    pub lyrics: String,
+    pub comment: String,
+    ///Where the song starts and ends in the file in seconds.
+    ///
+    ///Only set for tracks split from a cue sheet, an end of zero plays to the end of the file.
+    pub start: f32,
+    pub end: f32,
+    ///The `SCANNER_VERSION` that read the song, older songs are read again when rescanning.
+    pub scanner: u16,
 }
 
 impl Serialize for Song {
     fn serialize(&self) -> String {
+        self.line(true)
+    }
+}
+
+impl Song {
+    ///The song as a line of text, lyrics and comment are only written when `text` is true.
+    fn line(&self, text: bool) -> String {
         use std::fmt::Write;
 
         let mut buffer = String::new();
@@ -30,14 +83,39 @@ impl Serialize for Song {
 
         let result = writeln!(
             &mut buffer,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
             escape(&self.title),
             escape(&self.album),
             escape(&self.artist),
             self.disc_number,
             self.track_number,
-            escape(&self.path),
+            encode_path(&self.path),
             gain,
+            self.year,
+            escape(&join_values(&self.genres)),
+            escape(&join_values(&self.composers)),
+            escape(&join_values(&self.track_artists)),
+            self.duration,
+            self.sample_rate,
+            self.bit_depth,
+            self.bitrate,
+            self.channels,
+            self.file_size,
+            self.modified,
+            encode_path(&self.root),
+            self.track_total,
+            self.disc_total,
+            escape(&self.album_artist),
+            self.compilation as u8,
+            escape(&self.artist_sort),
+            escape(&self.album_artist_sort),
+            escape(&self.album_sort),
+            self.id.0,
+            escape_text(if text { &self.lyrics } else { "" }),
+            escape_text(if text { &self.comment } else { "" }),
+            self.start,
+            self.end,
+            self.scanner,
         );
 
         match result {
@@ -51,36 +129,16 @@ impl Deserialize for Song {
     type Error = Box<dyn std::error::Error>;
 
     fn deserialize(s: &str) -> Result<Self, Self::Error> {
-        if s.is_empty() {
-            return Err("Empty song")?;
-        }
-
-        //`file.lines()` will not include newlines
-        //but song.to_string() will.
-        let s = if s.as_bytes().last() == Some(&b'\n') {
-            &s[..s.len() - 1]
-        } else {
-            s
-        };
-
-        let mut parts = s.split('\t');
-        Ok(Song {
-            title: parts.next().ok_or("Missing title")?.to_string(),
-            album: parts.next().ok_or("Missing album")?.to_string(),
-            artist: parts.next().ok_or("Missing artist")?.to_string(),
-            disc_number: parts.next().ok_or("Missing disc_number")?.parse::<u8>()?,
-            track_number: parts.next().ok_or("Missing track_number")?.parse::<u8>()?,
-            path: parts.next().ok_or("Missing path")?.to_string(),
-            gain: parts.next().ok_or("Missing gain")?.parse::<f32>()?,
-        })
+        Song::parse(s, &SONG_COLUMNS)
     }
 }
 
+///Queues and playlists leave out lyrics and comments, they're looked up in the database by ID.
 impl Serialize for Vec<Song> {
     fn serialize(&self) -> String {
         let mut buffer = String::new();
         for song in self {
-            buffer.push_str(&song.serialize());
+            buffer.push_str(&song.line(false));
         }
         buffer
     }
@@ -94,9 +152,426 @@ impl Deserialize for Vec<Song> {
     }
 }
 
+///The order songs are serialized in.
+///
+///Only append to this, see `migrate`.
+pub const SONG_COLUMNS: [&str; 32] = [
+    "title",
+    "album",
+    "artist",
+    "disc_number",
+    "track_number",
+    "path",
+    "gain",
+    "year",
+    "genre",
+    "composer",
+    "track_artist",
+    "duration",
+    "sample_rate",
+    "bit_depth",
+    "bitrate",
+    "channels",
+    "file_size",
+    "modified",
+    "root",
+    "track_total",
+    "disc_total",
+    "album_artist",
+    "compilation",
+    "artist_sort",
+    "album_artist_sort",
+    "album_sort",
+    "id",
+    "lyrics",
+    "comment",
+    "start",
+    "end",
+    "scanner",
+];
+
+pub const MIGRATIONS: [migrate::Migration; migrate::VERSION as usize - 1] = [
+    migrate::unchanged,
+    migrate::unchanged,
+    escape_database_paths,
+    migrate::unchanged,
+    migrate::unchanged,
+    migrate::unchanged,
+    migrate::unchanged,
+    migrate::unchanged,
+    migrate::unchanged,
+];
+
+///Parse every song line, returning the songs and the lines that couldn't be read.
+pub fn parse_songs<S: AsRef<str>>(body: &str, columns: &[S]) -> (Vec<Song>, Vec<String>) {
+    let mut songs = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, line) in body.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        match Song::parse(line, columns) {
+            Ok(song) => songs.push(song),
+            Err(err) => errors.push(format!("Line {}: ({err}) {}", i + 1, line)),
+        }
+    }
+
+    (songs, errors)
+}
+
+///Parse the contents of `gonk.db`, upgrading older versions.
+///
+///Returns the songs and the lines that couldn't be read.
+pub fn deserialize_database(s: &str) -> Result<(Vec<Song>, Vec<String>), Box<dyn Error>> {
+    let document = migrate::read(s, &MIGRATIONS)?;
+    Ok(parse_songs(&document.body, &document.columns))
+}
+
+pub fn serialize_database(songs: &[Song]) -> String {
+    let mut buffer = migrate::header();
+    for song in songs {
+        buffer.push_str(&song.serialize());
+    }
+    buffer
+}
+
+///Encode a path so it can be stored as text.
+///
+///`%`, tabs and newlines are percent-encoded, and so is anything that isn't Unicode:
+///bytes that aren't valid UTF-8 on Unix as `%XX` and unpaired surrogates on Windows as `%uXXXX`.
+pub fn encode_path(path: &Path) -> Cow<'_, str> {
+    if let Some(s) = path.to_str() {
+        if !s.contains(is_escaped) {
+            return Cow::Borrowed(s);
+        }
+    }
+
+    let mut buffer = String::with_capacity(path.as_os_str().len());
+    push_os_str(&mut buffer, path.as_os_str());
+    Cow::Owned(buffer)
+}
+
+fn is_escaped(c: char) -> bool {
+    matches!(c, '%' | '\t' | '\n' | '\r')
+}
+
+fn push_str(buffer: &mut String, s: &str) {
+    for c in s.chars() {
+        if is_escaped(c) {
+            buffer.push_str(&format!("%{:02X}", c as u8));
+        } else {
+            buffer.push(c);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn push_os_str(buffer: &mut String, s: &std::ffi::OsStr) {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut bytes = s.as_bytes();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => {
+                push_str(buffer, s);
+                break;
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                push_str(buffer, std::str::from_utf8(valid).unwrap());
+
+                let len = err.error_len().unwrap_or(rest.len());
+                for byte in &rest[..len] {
+                    buffer.push_str(&format!("%{byte:02X}"));
+                }
+                bytes = &rest[len..];
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn push_os_str(buffer: &mut String, s: &std::ffi::OsStr) {
+    use std::os::windows::ffi::OsStrExt;
+
+    for c in char::decode_utf16(s.encode_wide()) {
+        match c {
+            Ok(c) => push_str(buffer, c.encode_utf8(&mut [0; 4])),
+            Err(err) => buffer.push_str(&format!("%u{:04X}", err.unpaired_surrogate())),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn push_os_str(buffer: &mut String, s: &std::ffi::OsStr) {
+    push_str(buffer, &s.to_string_lossy());
+}
+
+///Decode a path stored with `encode_path`.
+pub fn decode_path(s: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if !s.contains('%') {
+        return Ok(PathBuf::from(s));
+    }
+
+    let mut bytes = Vec::with_capacity(s.len());
+    //Unpaired surrogates and where they are in `bytes`.
+    let mut surrogates = Vec::new();
+    let hex = |digits: Option<&str>| -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_str_radix(
+            digits.ok_or("Invalid path escape")?,
+            16,
+        )?)
+    };
+
+    let mut i = 0;
+    while let Some(&byte) = s.as_bytes().get(i) {
+        if byte != b'%' {
+            bytes.push(byte);
+            i += 1;
+        } else if s[i + 1..].starts_with('u') {
+            surrogates.push((bytes.len(), hex(s.get(i + 2..i + 6))?));
+            i += 6;
+        } else {
+            bytes.push(hex(s.get(i + 1..i + 3))? as u8);
+            i += 3;
+        }
+    }
+
+    Ok(PathBuf::from(os_string(bytes, surrogates)?))
+}
+
+#[cfg(unix)]
+fn os_string(
+    bytes: Vec<u8>,
+    surrogates: Vec<(usize, u16)>,
+) -> Result<std::ffi::OsString, Box<dyn Error>> {
+    use std::os::unix::ffi::OsStringExt;
+
+    if !surrogates.is_empty() {
+        return Err("Unpaired surrogate in path")?;
+    }
+    Ok(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+fn os_string(
+    bytes: Vec<u8>,
+    surrogates: Vec<(usize, u16)>,
+) -> Result<std::ffi::OsString, Box<dyn Error>> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut wide = Vec::with_capacity(bytes.len());
+    let mut start = 0;
+    for (end, surrogate) in surrogates {
+        wide.extend(std::str::from_utf8(&bytes[start..end])?.encode_utf16());
+        wide.push(surrogate);
+        start = end;
+    }
+    wide.extend(std::str::from_utf8(&bytes[start..])?.encode_utf16());
+    Ok(std::ffi::OsString::from_wide(&wide))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_string(
+    bytes: Vec<u8>,
+    surrogates: Vec<(usize, u16)>,
+) -> Result<std::ffi::OsString, Box<dyn Error>> {
+    if !surrogates.is_empty() {
+        return Err("Unpaired surrogate in path")?;
+    }
+    Ok(String::from_utf8(bytes)?.into())
+}
+
+///Version 4 percent-encodes paths, so `%` in older paths needs to be escaped.
+///
+///The first `skip` lines aren't songs.
+pub fn escape_paths(document: &mut migrate::Document, skip: usize) -> Result<(), Box<dyn Error>> {
+    if !document.body.contains('%') {
+        return Ok(());
+    }
+
+    let columns: Vec<usize> = document
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| matches!(column.as_str(), "path" | "root"))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut body = String::with_capacity(document.body.len());
+    for (i, line) in document.body.lines().enumerate() {
+        if i < skip {
+            body.push_str(line);
+        } else {
+            let parts: Vec<Cow<str>> = line
+                .split('\t')
+                .enumerate()
+                .map(|(i, part)| {
+                    if columns.contains(&i) {
+                        Cow::Owned(part.replace('%', "%25"))
+                    } else {
+                        Cow::Borrowed(part)
+                    }
+                })
+                .collect();
+            body.push_str(&parts.join("\t"));
+        }
+        body.push('\n');
+    }
+
+    document.body = Cow::Owned(body);
+    Ok(())
+}
+
+fn escape_database_paths(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    escape_paths(document, 0)
+}
+
+///Is the database stored in an older format.
+pub fn is_outdated(s: &str) -> bool {
+    !s.starts_with(&migrate::header())
+}
+
 pub const UNKNOWN_TITLE: &str = "Unknown Title";
 pub const UNKNOWN_ALBUM: &str = "Unknown Album";
 pub const UNKNOWN_ARTIST: &str = "Unknown Artist";
+///Default artist for compilations, see `Settings::various_artists`.
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+///Default separators for tags that hold several values in one string, see `Settings::separators`.
+pub const SEPARATORS: [&str; 2] = ["feat.", ";"];
+///Bump when the scanner reads something new, so songs from older scans are read again.
+pub const SCANNER_VERSION: u16 = 1;
+///Separates the values of multi-valued tags on disk.
+pub const VALUE_SEPARATOR: char = '\u{1f}';
+
+///Escape text that can have several lines, unlike `escape` nothing is lost.
+pub fn escape_text(s: &str) -> Cow<'_, str> {
+    if !s.contains(['\\', '\n', '\r', '\t']) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut buffer = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '\\' => buffer.push_str("\\\\"),
+            '\n' => buffer.push_str("\\n"),
+            '\t' => buffer.push_str("\\t"),
+            '\r' => {}
+            c => buffer.push(c),
+        }
+    }
+    Cow::Owned(buffer)
+}
+
+///Decode text stored with `escape_text`.
+pub fn unescape_text(s: &str) -> String {
+    let mut buffer = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            buffer.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => buffer.push('\n'),
+            Some('t') => buffer.push('\t'),
+            Some(c) => buffer.push(c),
+            None => buffer.push('\\'),
+        }
+    }
+    buffer
+}
+
+pub fn join_values(values: &[String]) -> String {
+    values.join(&VALUE_SEPARATOR.to_string())
+}
+
+pub fn split_values(s: &str) -> Vec<String> {
+    s.split(VALUE_SEPARATOR)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+        .collect()
+}
+
+///Split a tag like "Artist feat. Guest" into each value.
+///
+///Separators must be followed by whitespace, so "AC/DC" isn't split on "/" but "Rock / Pop" is.
+///Separators starting with a letter, like "feat.", must also follow whitespace.
+///Whitespace inside the separator counts, like in " & ".
+pub fn split_tag(s: &str, separators: &[String]) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < s.len() {
+        let rest = &s[i..];
+        let separator = separators.iter().find(|sep| {
+            let Some(candidate) = rest.get(..sep.len()) else {
+                return false;
+            };
+            if sep.is_empty() || !candidate.eq_ignore_ascii_case(sep) {
+                return false;
+            }
+            let before = s[..i].chars().next_back();
+            let after = rest[sep.len()..].chars().next();
+            let space_before =
+                !sep.starts_with(char::is_alphabetic) || before.is_some_and(char::is_whitespace);
+            let space_after =
+                sep.ends_with(char::is_whitespace) || after.is_none_or(char::is_whitespace);
+            space_before && space_after
+        });
+
+        match separator {
+            Some(sep) => {
+                values.push(s[start..i].trim());
+                i += sep.len();
+                start = i;
+            }
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    values.push(s[start..].trim());
+
+    values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+        .collect()
+}
+
+///Identifies a song in the library.
+///
+///Derived from the path when the song is first scanned and kept across rescans, zero if unassigned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SongId(pub u64);
+
+impl SongId {
+    ///64-bit FNV-1a hash of the encoded path, so it's the same on every platform and version.
+    pub fn new(path: &Path) -> Self {
+        Self::hash(encode_path(path).bytes())
+    }
+
+    ///Tracks split from a cue sheet share a path, so the track number is included.
+    pub fn track(path: &Path, number: u16) -> Self {
+        let suffix = format!("#{number}");
+        Self::hash(encode_path(path).bytes().chain(suffix.bytes()))
+    }
+
+    ///Another ID for a song whose ID is already taken.
+    pub fn rehash(self) -> Self {
+        Self::hash(self.0.to_le_bytes().into_iter())
+    }
+
+    fn hash(bytes: impl Iterator<Item = u8>) -> Self {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        Self(hash)
+    }
+}
 
 impl Song {
     pub fn default() -> Self {
@@ -106,8 +581,33 @@ impl Song {
             artist: UNKNOWN_ARTIST.to_string(),
             disc_number: 1,
             track_number: 1,
-            path: String::new(),
+            path: PathBuf::new(),
             gain: 0.0,
+            year: 0,
+            genres: Vec::new(),
+            composers: Vec::new(),
+            track_artists: Vec::new(),
+            duration: 0.0,
+            sample_rate: 0,
+            bit_depth: 0,
+            bitrate: 0,
+            channels: 0,
+            file_size: 0,
+            modified: 0,
+            root: PathBuf::new(),
+            track_total: 0,
+            disc_total: 0,
+            album_artist: String::new(),
+            compilation: false,
+            artist_sort: String::new(),
+            album_artist_sort: String::new(),
+            album_sort: String::new(),
+            id: SongId::default(),
+            lyrics: String::new(),
+            comment: String::new(),
+            start: 0.0,
+            end: 0.0,
+            scanner: 0,
         }
     }
     pub fn example() -> Self {
@@ -117,16 +617,173 @@ impl Song {
             artist: "artist".to_string(),
             disc_number: 1,
             track_number: 1,
-            path: "path".to_string(),
+            path: PathBuf::from("path"),
             gain: 1.0,
+            year: 2000,
+            genres: vec!["genre".to_string()],
+            composers: vec!["composer".to_string()],
+            track_artists: vec!["track artist".to_string()],
+            duration: 180.5,
+            sample_rate: 44100,
+            bit_depth: 16,
+            bitrate: 1411,
+            channels: 2,
+            file_size: 31_000_000,
+            modified: 1_700_000_000_000_000_000,
+            root: PathBuf::from("root"),
+            track_total: 12,
+            disc_total: 1,
+            album_artist: "artist".to_string(),
+            compilation: false,
+            artist_sort: String::new(),
+            album_artist_sort: String::new(),
+            album_sort: String::new(),
+            id: SongId::new(Path::new("path")),
+            lyrics: "[00:01.00]first line\n[00:02.50]second\tline".to_string(),
+            comment: "comment".to_string(),
+            start: 0.0,
+            end: 0.0,
+            scanner: SCANNER_VERSION,
         }
     }
+
+    ///Split artists, genres and composers stored in a single string.
+    ///
+    ///The artist is left alone, see `vdb::Database` for how songs are filed.
+    pub fn split_tags(&mut self, separators: &[String]) {
+        let split = |values: &[String]| -> Vec<String> {
+            let mut out: Vec<String> = Vec::new();
+            for value in values.iter().flat_map(|v| split_tag(v, separators)) {
+                if !out.contains(&value) {
+                    out.push(value);
+                }
+            }
+            out
+        };
+        self.track_artists = split(&self.track_artists);
+        self.genres = split(&self.genres);
+        self.composers = split(&self.composers);
+    }
+
+    ///Parse a song stored with the given columns.
+    ///
+    ///Columns that aren't stored are left as unknown and unknown columns are ignored.
+    pub fn parse<S: AsRef<str>>(s: &str, columns: &[S]) -> Result<Self, Box<dyn Error>> {
+        if s.is_empty() {
+            return Err("Empty song")?;
+        }
+
+        //`file.lines()` will not include newlines
+        //but song.to_string() will.
+        let s = if s.as_bytes().last() == Some(&b'\n') {
+            &s[..s.len() - 1]
+        } else {
+            s
+        };
+
+        let mut song = Song::default();
+        let mut parts = s.split('\t');
+
+        for column in columns {
+            let column = column.as_ref();
+            let value = parts.next().ok_or_else(|| format!("Missing {column}"))?;
+
+            match column {
+                "title" => song.title = value.to_string(),
+                "album" => song.album = value.to_string(),
+                "artist" => song.artist = value.to_string(),
+                "disc_number" => song.disc_number = value.parse()?,
+                "track_number" => song.track_number = value.parse()?,
+                "path" => song.path = decode_path(value)?,
+                "gain" => song.gain = value.parse()?,
+                "year" => song.year = value.parse()?,
+                "genre" => song.genres = split_values(value),
+                "composer" => song.composers = split_values(value),
+                "track_artist" => song.track_artists = split_values(value),
+                "duration" => song.duration = value.parse()?,
+                "sample_rate" => song.sample_rate = value.parse()?,
+                "bit_depth" => song.bit_depth = value.parse()?,
+                "bitrate" => song.bitrate = value.parse()?,
+                "channels" => song.channels = value.parse()?,
+                "file_size" => song.file_size = value.parse()?,
+                "modified" => song.modified = value.parse()?,
+                "root" => song.root = decode_path(value)?,
+                "track_total" => song.track_total = value.parse()?,
+                "disc_total" => song.disc_total = value.parse()?,
+                "album_artist" => song.album_artist = value.to_string(),
+                "compilation" => song.compilation = value == "1",
+                "artist_sort" => song.artist_sort = value.to_string(),
+                "album_artist_sort" => song.album_artist_sort = value.to_string(),
+                "album_sort" => song.album_sort = value.to_string(),
+                "id" => song.id = SongId(value.parse()?),
+                "lyrics" => song.lyrics = unescape_text(value),
+                "comment" => song.comment = unescape_text(value),
+                "start" => song.start = value.parse()?,
+                "end" => song.end = value.parse()?,
+                "scanner" => song.scanner = value.parse()?,
+                _ => {}
+            }
+        }
+
+        if song.path.as_os_str().is_empty() {
+            return Err("Missing path")?;
+        }
+
+        //Older files didn't store the ID.
+        if song.id == SongId::default() {
+            song.id = SongId::new(&song.path);
+        }
+
+        Ok(song)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Album {
     pub title: String,
     pub songs: Vec<Song>,
+    ///Set for albums that are grouped by folder, so compilations with the same title stay apart.
+    pub folder: Option<PathBuf>,
+}
+
+impl Album {
+    ///The number of tracks found and the number the totals say the album has.
+    ///
+    ///`None` if none of the songs have a track total.
+    ///A disc that's missing entirely only counts as one missing track since its total is unknown.
+    pub fn completeness(&self) -> Option<(usize, usize)> {
+        //Disc number and the track numbers found on it.
+        let mut discs: BTreeMap<u16, (u16, HashSet<u16>)> = BTreeMap::new();
+        let mut disc_total = 0;
+
+        for song in &self.songs {
+            let (total, tracks) = discs.entry(song.disc_number).or_default();
+            *total = (*total).max(song.track_total);
+            tracks.insert(song.track_number);
+            disc_total = disc_total.max(song.disc_total as usize);
+        }
+
+        if discs.values().all(|(total, _)| *total == 0) {
+            return None;
+        }
+
+        let found = discs.values().map(|(_, tracks)| tracks.len()).sum();
+        let expected = discs
+            .values()
+            .map(|(total, tracks)| tracks.len().max(*total as usize))
+            .sum::<usize>()
+            + disc_total.saturating_sub(discs.len());
+
+        Some((found, expected))
+    }
+
+    ///Does the album have every track and disc its totals say it should.
+    pub fn is_complete(&self) -> bool {
+        match self.completeness() {
+            Some((found, expected)) => found == expected,
+            None => true,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -135,117 +792,318 @@ pub struct Artist {
 }
 
 impl TryFrom<&Path> for Song {
-    type Error = String;
+    type Error = ScanError;
 
     fn try_from(path: &Path) -> Result<Self, Self::Error> {
-        let extension = path.extension().ok_or("Path is not audio")?;
+        let extension = path
+            .extension()
+            .ok_or_else(|| ScanError::UnsupportedFormat("Path is not audio.".to_string()))?;
 
-        if extension != "flac" {
-            use symphonia::{
-                core::{formats::FormatOptions, io::*, meta::*, probe::Hint},
-                default::get_probe,
-            };
+        let extension = extension.to_str().map(|ex| ex.to_ascii_lowercase());
 
-            let file = match File::open(path) {
-                Ok(file) => file,
-                Err(err) => return Err(format!("Error: ({err}) @ {}", path.to_string_lossy())),
-            };
+        let song = match extension.as_deref() {
+            Some("flac") => read_metadata(path).map_err(ScanError::from),
+            //Fallback to symphonia when there are no ID3 tags.
+            Some("mp3") => read_id3(path).or_else(|_| read_symphonia(path)),
+            Some("ogg") => read_ogg(path).or_else(|_| read_symphonia(path)),
+            _ => read_symphonia(path),
+        }?;
 
-            let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
-
-            let mut probe = match get_probe().format(
-                &Hint::new(),
-                mss,
-                &FormatOptions::default(),
-                &MetadataOptions {
-                    limit_visual_bytes: Limit::Maximum(1),
-                    ..Default::default()
-                },
-            ) {
-                Ok(probe) => probe,
-                Err(err) => return Err(format!("Error: ({err}) @ {}", path.to_string_lossy()))?,
-            };
+        Ok(finish(song, path))
+    }
+}
 
-            let mut title = String::from("Unknown Title");
-            let mut album = String::from("Unknown Album");
-            let mut artist = String::from("Unknown Artist");
-            let mut track_number = 1;
-            let mut disc_number = 1;
-            let mut gain = 0.0;
+///Fill in everything the readers don't know about.
+fn finish(mut song: Song, path: &Path) -> Song {
+    song.id = SongId::new(path);
+    song.scanner = SCANNER_VERSION;
 
-            let mut metadata_revision = probe.format.metadata();
-            let mut metadata = probe.metadata.get();
-            let mut m = None;
+    if let Some(lyrics) = lyrics::sidecar(path) {
+        song.lyrics = lyrics;
+    }
 
-            if let Some(metadata) = metadata_revision.skip_to_latest() {
-                m = Some(metadata);
-            };
+    if let Ok(metadata) = fs::metadata(path) {
+        song.file_size = metadata.len();
+        song.modified = modified(&metadata);
+    }
 
-            if let Some(metadata) = &mut metadata {
-                if let Some(metadata) = metadata.skip_to_latest() {
-                    m = Some(metadata)
-                };
-            }
+    if song.bitrate == 0 && song.duration > 0.0 {
+        song.bitrate = (song.file_size as f32 * 8.0 / song.duration / 1000.0) as u32;
+    }
 
-            if let Some(metadata) = m {
-                for tag in metadata.tags() {
-                    if let Some(std_key) = tag.std_key {
-                        match std_key {
-                            StandardTagKey::AlbumArtist => artist = tag.value.to_string(),
-                            StandardTagKey::Artist if artist == "Unknown Artist" => {
-                                artist = tag.value.to_string()
-                            }
-                            StandardTagKey::Album => album = tag.value.to_string(),
-                            StandardTagKey::TrackTitle => title = tag.value.to_string(),
-                            StandardTagKey::TrackNumber => {
-                                let num = tag.value.to_string();
-                                if let Some((num, _)) = num.split_once('/') {
-                                    track_number = num.parse().unwrap_or(1);
-                                } else {
-                                    track_number = num.parse().unwrap_or(1);
-                                }
-                            }
-                            StandardTagKey::DiscNumber => {
-                                let num = tag.value.to_string();
-                                if let Some((num, _)) = num.split_once('/') {
-                                    disc_number = num.parse().unwrap_or(1);
-                                } else {
-                                    disc_number = num.parse().unwrap_or(1);
-                                }
-                            }
-                            StandardTagKey::ReplayGainTrackGain => {
-                                let tag = tag.value.to_string();
-                                let (_, value) =
-                                    tag.split_once(' ').ok_or("Invalid replay gain.")?;
-                                let db = value.parse().unwrap_or(0.0);
-                                gain = 10.0f32.powf(db / 20.0);
-                            }
-                            _ => (),
+    song
+}
+
+///Read every song in a file, FLAC files with an embedded cue sheet have a song per track.
+///
+///The warning is set when a cue sheet was ignored.
+pub fn read_songs(path: &Path) -> Result<(Vec<Song>, Option<ScanError>), ScanError> {
+    let is_flac = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"));
+
+    if !is_flac {
+        return Ok((vec![Song::try_from(path)?], None));
+    }
+
+    let flac = read_flac(path)?;
+    let warning = flac.cue_error.clone().map(ScanError::InvalidCueSheet);
+    let song = finish(flac.song.clone(), path);
+    let tracks = flac.tracks(&song);
+
+    if tracks.is_empty() {
+        Ok((vec![song], warning))
+    } else {
+        Ok((tracks, warning))
+    }
+}
+
+///Read the metadata with a full symphonia probe.
+///
+///This is much slower than the native readers.
+pub fn read_symphonia(path: &Path) -> Result<Song, ScanError> {
+    use symphonia::{
+        core::{formats::FormatOptions, io::*, meta::*, probe::Hint},
+        default::get_probe,
+    };
+
+    let file = File::open(path)?;
+
+    let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+
+    let mut probe = match get_probe().format(
+        &Hint::new(),
+        mss,
+        &FormatOptions::default(),
+        //Covers are read separately, see `cover`.
+        &MetadataOptions {
+            limit_visual_bytes: Limit::Maximum(1),
+            ..Default::default()
+        },
+    ) {
+        Ok(probe) => probe,
+        Err(err) => return Err(ScanError::UnsupportedFormat(err.to_string())),
+    };
+
+    let mut song = Song::default();
+    song.path = path.to_path_buf();
+
+    if let Some(track) = probe.format.default_track() {
+        let params = &track.codec_params;
+        song.sample_rate = params.sample_rate.unwrap_or_default();
+        song.bit_depth = params.bits_per_sample.unwrap_or_default() as u8;
+        song.channels = params.channels.map(|c| c.count()).unwrap_or_default() as u8;
+
+        if let (Some(n_frames), Some(sample_rate)) = (params.n_frames, params.sample_rate) {
+            song.duration = n_frames as f32 / sample_rate as f32;
+        }
+    }
+
+    let mut metadata_revision = probe.format.metadata();
+    let mut metadata = probe.metadata.get();
+    let mut m = None;
+
+    if let Some(metadata) = metadata_revision.skip_to_latest() {
+        m = Some(metadata);
+    };
+
+    if let Some(metadata) = &mut metadata {
+        if let Some(metadata) = metadata.skip_to_latest() {
+            m = Some(metadata)
+        };
+    }
+
+    if let Some(metadata) = m {
+        for tag in metadata.tags() {
+            if let Some(std_key) = tag.std_key {
+                match std_key {
+                    StandardTagKey::AlbumArtist => {
+                        song.artist = tag.value.to_string();
+                        song.album_artist = tag.value.to_string();
+                    }
+                    StandardTagKey::Compilation => {
+                        song.compilation = is_set(&tag.value.to_string())
+                    }
+                    StandardTagKey::SortArtist => song.artist_sort = tag.value.to_string(),
+                    StandardTagKey::SortAlbumArtist => {
+                        song.album_artist_sort = tag.value.to_string()
+                    }
+                    StandardTagKey::SortAlbum => song.album_sort = tag.value.to_string(),
+                    StandardTagKey::Artist => {
+                        if song.artist == UNKNOWN_ARTIST {
+                            song.artist = tag.value.to_string();
+                        }
+                        song.track_artists.push(tag.value.to_string());
+                    }
+                    StandardTagKey::Album => song.album = tag.value.to_string(),
+                    StandardTagKey::TrackTitle => song.title = tag.value.to_string(),
+                    StandardTagKey::TrackNumber => {
+                        let (n, total) = number(&tag.value.to_string());
+                        song.track_number = n.unwrap_or(1);
+                        if let Some(total) = total {
+                            song.track_total = total;
+                        }
+                    }
+                    StandardTagKey::DiscNumber => {
+                        let (n, total) = number(&tag.value.to_string());
+                        song.disc_number = n.unwrap_or(1);
+                        if let Some(total) = total {
+                            song.disc_total = total;
+                        }
+                    }
+                    StandardTagKey::TrackTotal => {
+                        if let (Some(total), _) = number(&tag.value.to_string()) {
+                            song.track_total = total;
                         }
                     }
+                    StandardTagKey::DiscTotal => {
+                        if let (Some(total), _) = number(&tag.value.to_string()) {
+                            song.disc_total = total;
+                        }
+                    }
+                    StandardTagKey::ReplayGainTrackGain => {
+                        //Invalid replay gain is ignored.
+                        let tag = tag.value.to_string();
+                        if let Some((_, value)) = tag.split_once(' ') {
+                            let db = value.parse().unwrap_or(0.0);
+                            song.gain = 10.0f32.powf(db / 20.0);
+                        }
+                    }
+                    StandardTagKey::Date | StandardTagKey::ReleaseDate if song.year == 0 => {
+                        song.year = year(&tag.value.to_string());
+                    }
+                    StandardTagKey::Genre => song.genres.push(tag.value.to_string()),
+                    StandardTagKey::Composer => song.composers.push(tag.value.to_string()),
+                    StandardTagKey::Lyrics => song.lyrics = tag.value.to_string(),
+                    StandardTagKey::Comment if song.comment.is_empty() => {
+                        song.comment = tag.value.to_string()
+                    }
+                    _ => (),
                 }
             }
+        }
+    }
+
+    Ok(song)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+///Why a file couldn't be added to the database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanError {
+    ///The file couldn't be opened or read.
+    Unreadable(String),
+    ///None of the readers support the file.
+    UnsupportedFormat(String),
+    ///The file is truncated or a header is invalid.
+    CorruptHeader(String),
+    ///Tags that weren't valid text. They're decoded lossily and the song is still added.
+    BadTagEncoding(Vec<&'static str>),
+    ///The embedded cue sheet couldn't be read, the file is added as one song.
+    InvalidCueSheet(String),
+}
 
-            Ok(Song {
-                title,
-                album,
-                artist,
-                disc_number,
-                track_number,
-                path: path.to_str().ok_or("Invalid UTF-8 in path.")?.to_string(),
-                gain,
-            })
+impl ScanError {
+    ///Was the song still added to the database.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            ScanError::BadTagEncoding(_) | ScanError::InvalidCueSheet(_)
+        )
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ScanError::Unreadable(_) => "Unreadable file",
+            ScanError::UnsupportedFormat(_) => "Unsupported format",
+            ScanError::CorruptHeader(_) => "Corrupt header",
+            ScanError::BadTagEncoding(_) => "Bad tag encoding",
+            ScanError::InvalidCueSheet(_) => "Invalid cue sheet",
+        }
+    }
+
+    pub fn details(&self) -> String {
+        match self {
+            ScanError::Unreadable(err)
+            | ScanError::UnsupportedFormat(err)
+            | ScanError::CorruptHeader(err)
+            | ScanError::InvalidCueSheet(err) => err.clone(),
+            ScanError::BadTagEncoding(tags) => tags.join(", "),
+        }
+    }
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.details())
+    }
+}
+
+impl Error for ScanError {}
+
+impl From<std::io::Error> for ScanError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            ScanError::CorruptHeader("Unexpected end of file.".to_string())
         } else {
-            read_metadata(path)
-                .map_err(|err| format!("Error: ({err}) @ {}", path.to_string_lossy()))
+            ScanError::Unreadable(err.to_string())
+        }
+    }
+}
+
+///The readers return `ScanError`s where the cause is known,
+///anything else went wrong while parsing so it's treated as a corrupt header.
+impl From<Box<dyn Error>> for ScanError {
+    fn from(err: Box<dyn Error>) -> Self {
+        match err.downcast::<ScanError>() {
+            Ok(err) => *err,
+            Err(err) => match err.downcast::<std::io::Error>() {
+                Ok(err) => ScanError::from(*err),
+                Err(err) => ScanError::CorruptHeader(err.to_string()),
+            },
         }
     }
 }
 
+///Check for tags the readers couldn't decode, they leave replacement characters behind.
+pub fn check_tags(song: &Song) -> Option<ScanError> {
+    let tags: Vec<&'static str> = [
+        ("title", &song.title),
+        ("album", &song.album),
+        ("artist", &song.artist),
+        ("genre", &join_values(&song.genres)),
+        ("composer", &join_values(&song.composers)),
+        ("track_artist", &join_values(&song.track_artists)),
+    ]
+    .into_iter()
+    .filter(|(_, value)| value.contains(char::REPLACEMENT_CHARACTER))
+    .map(|(tag, _)| tag)
+    .collect();
+
+    (!tags.is_empty()).then_some(ScanError::BadTagEncoding(tags))
+}
+
+///One line per file, written to `gonk.log`.
+pub fn scan_log(errors: &[(PathBuf, ScanError)]) -> String {
+    errors
+        .iter()
+        .map(|(path, err)| format!("{err} @ {}", path.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[derive(Debug)]
 pub enum ScanResult {
-    Completed,
-    CompletedWithErrors(Vec<String>),
+    Completed(ScanSummary),
+    ///Errors include warnings for songs that were still added.
+    CompletedWithErrors(ScanSummary, Vec<(PathBuf, ScanError)>),
     FileInUse,
 }
 
@@ -257,8 +1115,104 @@ pub fn reset() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn create(path: &str) -> JoinHandle<ScanResult> {
-    let path = path.to_string();
+enum Change {
+    Added,
+    Updated,
+    Unchanged,
+}
+
+///Nanoseconds since the unix epoch, zero if unavailable.
+pub fn modified(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+///Read the songs in the current database by path, ignoring any errors.
+///
+///Tracks split from a cue sheet share a path.
+fn existing_songs() -> HashMap<PathBuf, Vec<Song>> {
+    let Ok(string) = fs::read_to_string(database_path()) else {
+        return HashMap::new();
+    };
+    match deserialize_database(&string) {
+        Ok((songs, _)) => by_path(songs),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn by_path(songs: Vec<Song>) -> HashMap<PathBuf, Vec<Song>> {
+    let mut paths: HashMap<PathBuf, Vec<Song>> = HashMap::new();
+    for song in songs {
+        paths.entry(song.path.clone()).or_default().push(song);
+    }
+    paths
+}
+
+///Was the song read from this file by the current scanner.
+fn is_unchanged(old: &Song, metadata: &fs::Metadata) -> bool {
+    old.modified != 0
+        && old.scanner == SCANNER_VERSION
+        && old.modified == modified(metadata)
+        && old.file_size == metadata.len()
+}
+
+///Could `new` be `old` after its file was moved.
+///
+///The caller matches the file size, modification time and track number, the tags must match too.
+fn is_move(old: &Song, new: &Song) -> bool {
+    old.title == new.title && old.album == new.album && old.artist == new.artist
+}
+
+///Was the song's ID made from its own path, rather than kept from before it was moved.
+fn is_own_id(song: &Song) -> bool {
+    song.id == SongId::new(&song.path) || song.id == SongId::track(&song.path, song.track_number)
+}
+
+///Give songs that share an ID a new one.
+///
+///A moved song keeps the ID made from its old path, so a new file at that path would get the same ID.
+///The song that had its ID first keeps it.
+fn unique_ids(songs: &mut [Song]) {
+    let mut owners: HashMap<SongId, usize> = HashMap::new();
+    let mut clashes = Vec::new();
+
+    for (i, song) in songs.iter().enumerate() {
+        match owners.get(&song.id) {
+            None => {
+                owners.insert(song.id, i);
+            }
+            Some(&owner) if is_own_id(&songs[owner]) && !is_own_id(song) => {
+                owners.insert(song.id, i);
+                clashes.push(owner);
+            }
+            Some(_) => clashes.push(i),
+        }
+    }
+
+    for i in clashes {
+        let mut id = songs[i].id.rehash();
+        while owners.contains_key(&id) {
+            id = id.rehash();
+        }
+        owners.insert(id, i);
+        songs[i].id = id;
+    }
+}
+
+///The songs read from a file, how they changed and any warning.
+type ReadFile = (PathBuf, Vec<Song>, Change, Option<ScanError>);
+
+///Scan every music folder into one database.
+///
+///Only reads files that are new or have changed since the last scan.
+///Songs with the same modification time and size are reused from the existing database,
+///unless they were read by an older version of the scanner.
+pub fn create(folders: &[PathBuf]) -> JoinHandle<ScanResult> {
+    let folders = folders.to_vec();
     thread::spawn(move || {
         let mut db_path = database_path().to_path_buf();
         db_path.pop();
@@ -266,36 +1220,145 @@ pub fn create(path: &str) -> JoinHandle<ScanResult> {
 
         match File::create(&db_path) {
             Ok(file) => {
-                let paths: Vec<winwalk::DirEntry> = winwalk::walkdir(path, 0)
+                //(root, path) with files in nested folders only scanned once.
+                let mut seen = HashSet::new();
+                let paths: Vec<(PathBuf, PathBuf)> = folders
+                    .iter()
+                    .flat_map(|root| {
+                        walk::walk(root, &EXTENSIONS)
+                            .into_iter()
+                            .map(move |path| (root.clone(), path))
+                    })
+                    .filter(|(_, path)| seen.insert(path.clone()))
+                    .collect();
+
+                let existing = existing_songs();
+
+                //Adding, removing or renaming a lyrics file changes its folder,
+                //so only those folders are checked again. Edits are picked up by the watcher.
+                let scanned = fs::metadata(database_path()).map_or(0, |m| modified(&m));
+                let folders: HashSet<&Path> =
+                    paths.iter().filter_map(|(_, path)| path.parent()).collect();
+                let changed: HashSet<PathBuf> = folders
                     .into_iter()
+                    .filter(|folder| fs::metadata(folder).map_or(true, |m| modified(&m) >= scanned))
+                    .map(Path::to_path_buf)
+                    .collect();
+
+                //Songs that are no longer at their path may have been moved.
+                let found: HashSet<&PathBuf> = paths.iter().map(|(_, path)| path).collect();
+                let mut missing: HashMap<(u64, u64, u16), &Song> = existing
+                    .values()
                     .flatten()
-                    .filter(|entry| match entry.extension() {
-                        Some(ex) => {
-                            matches!(ex.to_str(), Some("flac" | "mp3" | "ogg"))
-                        }
-                        None => false,
-                    })
+                    .filter(|song| song.modified != 0 && !found.contains(&song.path))
+                    .map(|song| ((song.file_size, song.modified, song.track_number), song))
                     .collect();
 
-                let songs: Vec<_> = paths
+                let songs: Vec<Result<ReadFile, (PathBuf, ScanError)>> = paths
                     .into_par_iter()
-                    .map(|entry| Song::try_from(Path::new(&entry.path)))
-                    .collect();
+                    .map(|(root, path)| {
+                        let old = existing.get(&path);
 
-                let errors: Vec<String> = songs
-                    .iter()
-                    .filter_map(|song| {
-                        if let Err(err) = song {
-                            Some(err.clone())
+                        if let (Some(old), Ok(metadata)) = (old, fs::metadata(&path)) {
+                            if old.iter().all(|old| is_unchanged(old, &metadata)) {
+                                //Lyrics files can change without the song changing.
+                                let lyrics = match old.as_slice() {
+                                    [_] if path.parent().is_some_and(|f| changed.contains(f)) => {
+                                        lyrics::sidecar(&path)
+                                    }
+                                    _ => None,
+                                };
+                                let songs = old
+                                    .iter()
+                                    .map(|old| {
+                                        let mut song = old.clone();
+                                        song.root = root.clone();
+                                        if let Some(lyrics) = &lyrics {
+                                            song.lyrics = lyrics.clone();
+                                        }
+                                        song
+                                    })
+                                    .collect();
+                                return Ok((path, songs, Change::Unchanged, None));
+                            }
+                        }
+
+                        let change = if old.is_some() {
+                            Change::Updated
                         } else {
-                            None
+                            Change::Added
+                        };
+                        match read_songs(&path) {
+                            Ok((mut songs, warning)) => {
+                                for song in &mut songs {
+                                    song.root = root.clone();
+                                }
+                                if let (Some([old]), [song]) =
+                                    (old.map(Vec::as_slice), songs.as_mut_slice())
+                                {
+                                    song.id = old.id;
+                                }
+                                Ok((path, songs, change, warning))
+                            }
+                            Err(err) => Err((path, err)),
                         }
                     })
                     .collect();
 
-                let songs: Vec<Song> = songs.into_iter().flatten().collect();
+                let mut summary = ScanSummary::default();
+                let mut errors = Vec::new();
+                let mut found = Vec::with_capacity(songs.len());
+                let mut read = HashSet::new();
+
+                for result in songs {
+                    match result {
+                        Ok((path, songs, change, warning)) => {
+                            let old = existing.get(&path).map_or(0, Vec::len);
+                            match change {
+                                Change::Added => summary.added += songs.len(),
+                                Change::Updated => {
+                                    let kept = old.min(songs.len());
+                                    summary.updated += kept;
+                                    summary.added += songs.len() - kept;
+                                    summary.removed += old - kept;
+                                }
+                                Change::Unchanged => summary.unchanged += songs.len(),
+                            }
+                            if let Some(warning) = warning {
+                                errors.push((path.clone(), warning));
+                            }
+                            for mut song in songs {
+                                //Moved files keep their ID, each ID is only used once.
+                                if matches!(change, Change::Added) {
+                                    let key = (song.file_size, song.modified, song.track_number);
+                                    if missing.get(&key).is_some_and(|old| is_move(old, &song)) {
+                                        song.id = missing.remove(&key).unwrap().id;
+                                    }
+                                }
+                                if let Some(warning) = check_tags(&song) {
+                                    errors.push((song.path.clone(), warning));
+                                }
+                                found.push(song);
+                            }
+                            read.insert(path);
+                        }
+                        Err(err) => errors.push(err),
+                    }
+                }
+                let mut songs = found;
+                unique_ids(&mut songs);
+
+                //Anything that wasn't found or couldn't be read was removed.
+                summary.removed += existing
+                    .iter()
+                    .filter(|(path, _)| !read.contains(*path))
+                    .map(|(_, songs)| songs.len())
+                    .sum::<usize>();
+
                 let mut writer = BufWriter::new(&file);
-                writer.write_all(&songs.serialize().into_bytes()).unwrap();
+                writer
+                    .write_all(serialize_database(&songs).as_bytes())
+                    .unwrap();
                 writer.flush().unwrap();
 
                 //Remove old database and replace it with new.
@@ -304,9 +1367,9 @@ pub fn create(path: &str) -> JoinHandle<ScanResult> {
                 // let _db = vdb::create().unwrap();
 
                 if errors.is_empty() {
-                    ScanResult::Completed
+                    ScanResult::Completed(summary)
                 } else {
-                    ScanResult::CompletedWithErrors(errors)
+                    ScanResult::CompletedWithErrors(summary, errors)
                 }
             }
             Err(_) => ScanResult::FileInUse,
@@ -314,6 +1377,294 @@ pub fn create(path: &str) -> JoinHandle<ScanResult> {
     })
 }
 
+///Write the database, replacing the old one.
+pub fn save(songs: &[Song]) -> Result<(), Box<dyn Error>> {
+    let mut temp = database_path().to_path_buf();
+    temp.set_extension("tmp");
+    fs::write(&temp, serialize_database(songs))?;
+    fs::rename(temp, database_path())?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub summary: ScanSummary,
+    ///Old and new paths of moved songs.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    pub errors: Vec<(PathBuf, ScanError)>,
+}
+
+///File extensions that are scanned.
+pub const EXTENSIONS: [&str; 3] = ["flac", "mp3", "ogg"];
+
+fn is_audio(path: &Path) -> bool {
+    match path.extension().and_then(|ex| ex.to_str()) {
+        Some(ex) => EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ex)),
+        None => false,
+    }
+}
+
+///Would a scan read this file, hidden and ignored files are skipped like in `walk`.
+fn is_scanned(path: &Path, folders: &[PathBuf]) -> bool {
+    is_audio(path) && folders.iter().any(|folder| !walk::is_ignored(folder, path))
+}
+
+///Is `path` inside the folder `parent`.
+fn is_inside(path: &Path, parent: &Path) -> bool {
+    path != parent && path.starts_with(parent)
+}
+
+///The music folder containing `path`.
+fn root(path: &Path, folders: &[PathBuf]) -> PathBuf {
+    folders
+        .iter()
+        .find(|folder| path.starts_with(folder))
+        .cloned()
+        .unwrap_or_default()
+}
+
+///Remove every song in a music folder, returns the number of songs removed.
+///
+///Songs that are also inside one of the other music `folders` are kept.
+pub fn remove_folder(folder: &Path, folders: &[PathBuf]) -> Result<usize, Box<dyn Error>> {
+    let string = match fs::read_to_string(database_path()) {
+        Ok(string) => string,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err)?,
+    };
+    let (mut songs, _) = deserialize_database(&string)?;
+
+    let len = songs.len();
+    let moved = remove_songs(&mut songs, folder, folders);
+    let removed = len - songs.len();
+
+    if removed != 0 || moved != 0 {
+        save(&songs)?;
+    }
+
+    Ok(removed)
+}
+
+///Remove the songs in `folder`, songs in a nested music folder are moved to it.
+///
+///Returns the number of songs that were moved.
+fn remove_songs(songs: &mut Vec<Song>, folder: &Path, folders: &[PathBuf]) -> usize {
+    let others: Vec<PathBuf> = folders
+        .iter()
+        .filter(|other| other.as_path() != folder)
+        .cloned()
+        .collect();
+
+    let mut moved = 0;
+    songs.retain_mut(|song| {
+        if song.root != folder && !is_inside(&song.path, folder) {
+            return true;
+        }
+        let root = root(&song.path, &others);
+        if root.as_os_str().is_empty() {
+            return false;
+        }
+        if song.root != root {
+            song.root = root;
+            moved += 1;
+        }
+        true
+    });
+    moved
+}
+
+///Read a file again, replacing the songs it had. The old songs are kept if it can't be read.
+fn reread(file: &Path, old: Vec<Song>, folders: &[PathBuf], changes: &mut Changes) -> Vec<Song> {
+    let (mut new, warning) = match read_songs(file) {
+        Ok(read) => read,
+        Err(err) => {
+            changes.errors.push((file.to_path_buf(), err));
+            return old;
+        }
+    };
+    if let Some(warning) = warning {
+        changes.errors.push((file.to_path_buf(), warning));
+    }
+    for song in &mut new {
+        song.root = root(&song.path, folders);
+        if let Some(warning) = check_tags(song) {
+            changes.errors.push((song.path.clone(), warning));
+        }
+    }
+
+    if let ([old], [song]) = (old.as_slice(), new.as_mut_slice()) {
+        song.id = old.id;
+    }
+    let kept = old.len().min(new.len());
+    changes.summary.updated += old.iter().zip(&new).filter(|(old, new)| old != new).count();
+    changes.summary.added += new.len() - kept;
+    changes.summary.removed += old.len() - kept;
+    new
+}
+
+///The audio files a `.lrc` or `.txt` file holds the lyrics of, see `lyrics::sidecar`.
+fn lyrics_of(file: &Path, songs: &HashMap<PathBuf, Vec<Song>>) -> Vec<PathBuf> {
+    let is_sidecar = file
+        .extension()
+        .is_some_and(|ext| lyrics::SIDECAR_EXTENSIONS.iter().any(|s| ext == *s));
+    if !is_sidecar {
+        return Vec::new();
+    }
+    let stem = file.with_extension("");
+    songs
+        .keys()
+        .filter(|path| path.with_extension("") == stem)
+        .cloned()
+        .collect()
+}
+
+///Apply changes from the `watcher` to the database.
+///
+///This reads files, so it should be run off the UI thread.
+pub fn apply(events: &[watcher::Event], folders: &[PathBuf]) -> Result<Changes, Box<dyn Error>> {
+    use watcher::Event;
+
+    let (songs, _) = match fs::read_to_string(database_path()) {
+        Ok(string) => deserialize_database(&string)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+        Err(err) => return Err(err)?,
+    };
+    let mut songs = by_path(songs);
+    let mut changes = Changes::default();
+
+    for event in events {
+        match event {
+            Event::Changed(path) => {
+                let files = if path.is_dir() {
+                    walk::walk(path, &EXTENSIONS)
+                        .into_iter()
+                        .filter(|file| is_scanned(file, folders))
+                        .collect()
+                } else if is_scanned(path, folders) {
+                    vec![path.clone()]
+                } else {
+                    Vec::new()
+                };
+
+                for file in files {
+                    let old = songs.remove(&file).unwrap_or_default();
+                    let new = reread(&file, old, folders, &mut changes);
+                    if !new.is_empty() {
+                        songs.insert(file, new);
+                    }
+                }
+            }
+            Event::Removed(path) => match songs.remove(path) {
+                Some(old) => changes.summary.removed += old.len(),
+                None => songs.retain(|file, old| {
+                    let inside = is_inside(file, path);
+                    if inside {
+                        changes.summary.removed += old.len();
+                    }
+                    !inside
+                }),
+            },
+            Event::Renamed(from, to) => {
+                let moved: Vec<PathBuf> = if songs.contains_key(from) {
+                    vec![from.clone()]
+                } else {
+                    songs
+                        .keys()
+                        .filter(|file| is_inside(file, from))
+                        .cloned()
+                        .collect()
+                };
+
+                for file in &moved {
+                    let new = match file.strip_prefix(from) {
+                        Ok(rest) if file != from => to.join(rest),
+                        _ => to.clone(),
+                    };
+                    let mut moved = songs.remove(file).unwrap_or_default();
+                    //Renaming a song to something that isn't scanned removes it.
+                    if !is_scanned(&new, folders) {
+                        changes.summary.removed += moved.len();
+                        continue;
+                    }
+                    for song in &mut moved {
+                        changes.renamed.push((song.path.clone(), new.clone()));
+                        song.root = root(&new, folders);
+                        song.path = new.clone();
+                    }
+                    //Moved over another song.
+                    if let Some(old) = songs.insert(new, moved) {
+                        changes.summary.removed += old.len();
+                    }
+                }
+
+                //Files are often written to a temporary file and then renamed.
+                if moved.is_empty() && is_scanned(to, folders) {
+                    let old = songs.remove(to).unwrap_or_default();
+                    let new = reread(to, old, folders, &mut changes);
+                    if !new.is_empty() {
+                        songs.insert(to.clone(), new);
+                    }
+                }
+            }
+            Event::Overflow => {
+                //Events were lost, so every folder is scanned again.
+                let mut found = HashSet::new();
+                for folder in folders {
+                    for file in walk::walk(folder, &EXTENSIONS) {
+                        let unchanged = match (songs.get(&file), fs::metadata(&file)) {
+                            (Some(old), Ok(metadata)) => {
+                                old.iter().all(|old| is_unchanged(old, &metadata))
+                            }
+                            _ => false,
+                        };
+                        if !unchanged {
+                            let old = songs.remove(&file).unwrap_or_default();
+                            let new = reread(&file, old, folders, &mut changes);
+                            if !new.is_empty() {
+                                songs.insert(file.clone(), new);
+                            }
+                        }
+                        found.insert(file);
+                    }
+                }
+
+                songs.retain(|file, old| {
+                    let exists = found.contains(file);
+                    if !exists {
+                        changes.summary.removed += old.len();
+                    }
+                    exists
+                });
+            }
+        }
+
+        //Songs are read again when their lyrics file changes.
+        let files: Vec<PathBuf> = match event {
+            Event::Changed(path) | Event::Removed(path) => lyrics_of(path, &songs),
+            Event::Renamed(from, to) => [lyrics_of(from, &songs), lyrics_of(to, &songs)].concat(),
+            Event::Overflow => Vec::new(),
+        };
+        for file in files {
+            let old = songs.remove(&file).unwrap_or_default();
+            let new = reread(&file, old, folders, &mut changes);
+            if !new.is_empty() {
+                songs.insert(file, new);
+            }
+        }
+    }
+
+    if changes.summary != ScanSummary::default() || !changes.renamed.is_empty() {
+        let mut songs: Vec<Song> = songs.into_values().flatten().collect();
+        //Keeps the tracks of a cue sheet in order.
+        songs.sort_by(|a, b| a.path.cmp(&b.path));
+        unique_ids(&mut songs);
+        save(&songs)?;
+    }
+
+    Ok(changes)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{str::from_utf8_unchecked, time::Duration};
@@ -325,6 +1676,235 @@ mod tests {
         let song = Song::example();
         let string = song.serialize();
         assert_eq!(Song::deserialize(&string).unwrap(), song);
+
+        //Queues and playlists don't store lyrics or comments.
+        let string = vec![song.clone()].serialize();
+        let queued = Song::deserialize(&string).unwrap();
+        assert!(queued.lyrics.is_empty() && queued.comment.is_empty());
+        assert_eq!(queued.title, song.title);
+    }
+
+    #[test]
+    fn lyrics_files() {
+        let songs = by_path(vec![Song {
+            path: PathBuf::from("album/01 Song.flac"),
+            ..Song::example()
+        }]);
+        let song = [PathBuf::from("album/01 Song.flac")];
+        assert_eq!(lyrics_of(Path::new("album/01 Song.lrc"), &songs), song);
+        assert_eq!(lyrics_of(Path::new("album/01 Song.txt"), &songs), song);
+        assert!(lyrics_of(Path::new("album/01 Song.flac"), &songs).is_empty());
+        assert!(lyrics_of(Path::new("album/02 Song.lrc"), &songs).is_empty());
+    }
+
+    #[test]
+    fn multiple_values() {
+        let separators: Vec<String> = SEPARATORS.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            split_tag("Artist feat. Guest; Other", &separators),
+            ["Artist", "Guest", "Other"]
+        );
+        assert_eq!(split_tag("Defeat.", &separators), ["Defeat."]);
+        assert_eq!(split_tag("Pop;Rock", &separators), ["Pop;Rock"]);
+
+        let slash = ["/".to_string(), " & ".to_string()];
+        assert_eq!(split_tag("AC/DC", &slash), ["AC/DC"]);
+        assert_eq!(split_tag("Rock / Pop", &slash), ["Rock", "Pop"]);
+        assert_eq!(split_tag("A & B", &slash), ["A", "B"]);
+        assert_eq!(split_tag("", &separators), Vec::<String>::new());
+
+        let mut song = Song {
+            album_artist: String::new(),
+            artist: "A FEAT. B".to_string(),
+            track_artists: vec!["A FEAT. B".to_string(), "B".to_string()],
+            genres: vec!["Rock".to_string(), "Pop; Rock".to_string()],
+            ..Song::example()
+        };
+        song.split_tags(&separators);
+        assert_eq!(song.artist, "A FEAT. B");
+        assert_eq!(song.track_artists, ["A", "B"]);
+        assert_eq!(song.genres, ["Rock", "Pop"]);
+
+        let string = song.serialize();
+        assert_eq!(Song::deserialize(&string).unwrap(), song);
+    }
+
+    #[test]
+    fn inside() {
+        let music = Path::new("music");
+        assert!(is_inside(&music.join("song.flac"), music));
+        assert!(!is_inside(Path::new("music 2/song.flac"), music));
+    }
+
+    #[test]
+    fn nested_folders() {
+        let song = |path: &str| Song {
+            path: PathBuf::from(path),
+            root: PathBuf::from("music"),
+            ..Song::example()
+        };
+        let mut songs = vec![
+            song("music/a.flac"),
+            song("music/nested/b.flac"),
+            song("other/c.flac"),
+        ];
+        songs[2].root = PathBuf::from("other");
+
+        let folders = [PathBuf::from("music/nested"), PathBuf::from("other")];
+        assert_eq!(remove_songs(&mut songs, Path::new("music"), &folders), 1);
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].path, Path::new("music/nested/b.flac"));
+        assert_eq!(songs[0].root, Path::new("music/nested"));
+        assert_eq!(songs[1].path, Path::new("other/c.flac"));
+    }
+
+    #[test]
+    fn ids() {
+        let song = Song::example();
+        assert_eq!(song.id, SongId::new(Path::new("path")));
+        assert_ne!(song.id, SongId::new(Path::new("other")));
+
+        //Files written before IDs were stored get one from their path.
+        let line = "title\talbum\tartist\t1\t1\tmoved\t1";
+        let old = Song::parse(line, &SONG_COLUMNS[..7]).unwrap();
+        assert_eq!(old.id, SongId::new(Path::new("moved")));
+        //They're read again on the next scan.
+        assert_eq!(old.scanner, 0);
+        assert_eq!(
+            Song::deserialize(&song.serialize()).unwrap().scanner,
+            SCANNER_VERSION
+        );
+
+        let mut moved = song.clone();
+        moved.path = PathBuf::from("moved");
+        assert_eq!(Song::deserialize(&moved.serialize()).unwrap().id, song.id);
+    }
+
+    #[test]
+    fn moved_then_replaced() {
+        //"a.flac" was moved to "b.flac" and a new file was created at "a.flac".
+        let moved = Song {
+            path: PathBuf::from("b.flac"),
+            id: SongId::new(Path::new("a.flac")),
+            ..Song::example()
+        };
+        let new = Song {
+            path: PathBuf::from("a.flac"),
+            id: SongId::new(Path::new("a.flac")),
+            title: "new".to_string(),
+            ..Song::example()
+        };
+
+        for mut songs in [
+            vec![new.clone(), moved.clone()],
+            vec![moved.clone(), new.clone()],
+        ] {
+            unique_ids(&mut songs);
+            let moved = songs.iter().find(|song| song.path == moved.path).unwrap();
+            let new = songs.iter().find(|song| song.path == new.path).unwrap();
+            assert_eq!(moved.id, SongId::new(Path::new("a.flac")));
+            assert_ne!(new.id, moved.id);
+        }
+
+        //Songs with the same size and modification time aren't moves unless their tags match.
+        assert!(is_move(&Song::example(), &moved));
+        assert!(!is_move(&Song::example(), &new));
+
+        //Cue sheet tracks past 255 keep their own ID.
+        let track = Song {
+            path: PathBuf::from("image.flac"),
+            id: SongId::track(Path::new("image.flac"), 300),
+            track_number: 300,
+            ..Song::example()
+        };
+        assert!(is_own_id(&track));
+        assert_ne!(track.id, SongId::track(Path::new("image.flac"), 44));
+    }
+
+    #[test]
+    fn completeness() {
+        let song = |disc, track| Song {
+            disc_number: disc,
+            track_number: track,
+            track_total: 3,
+            disc_total: 2,
+            ..Song::example()
+        };
+        let mut album = Album {
+            title: "album".to_string(),
+            songs: vec![song(1, 1), song(1, 2), song(1, 3), song(2, 1)],
+            folder: None,
+        };
+        assert_eq!(album.completeness(), Some((4, 6)));
+        assert!(!album.is_complete());
+
+        album.songs.extend([song(2, 2), song(2, 3)]);
+        assert!(album.is_complete());
+
+        //The second disc is missing entirely.
+        album.songs.retain(|song| song.disc_number == 1);
+        assert_eq!(album.completeness(), Some((3, 4)));
+
+        album.songs = vec![Song::example(), Song::default()];
+        album.songs.iter_mut().for_each(|song| song.track_total = 0);
+        assert_eq!(album.completeness(), None);
+        assert!(album.is_complete());
+    }
+
+    #[test]
+    fn paths() {
+        let path = Path::new("100% music/song\tname.flac");
+        assert_eq!(encode_path(path), "100%25 music/song%09name.flac");
+        assert_eq!(decode_path(&encode_path(path)).unwrap(), path);
+        assert!(matches!(encode_path(Path::new("music")), Cow::Borrowed(_)));
+        assert!(decode_path("%2").is_err());
+
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            //Latin-1 encoded "café.flac".
+            let path = Path::new(OsStr::from_bytes(b"caf\xe9.flac"));
+            assert_eq!(encode_path(path), "caf%E9.flac");
+            assert_eq!(decode_path(&encode_path(path)).unwrap(), path);
+
+            let mut song = Song::example();
+            song.path = path.to_path_buf();
+            assert_eq!(Song::deserialize(&song.serialize()).unwrap(), song);
+        }
+
+        #[cfg(windows)]
+        {
+            use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+            //"a" followed by an unpaired surrogate.
+            let path = PathBuf::from(OsString::from_wide(&[0x61, 0xD800, 0x2E]));
+            assert_eq!(encode_path(&path), "a%uD800.");
+            assert_eq!(decode_path(&encode_path(&path)).unwrap(), path);
+        }
+
+        #[cfg(unix)]
+        assert!(decode_path("a%uD800").is_err());
+        assert!(decode_path("a%uD8").is_err());
+    }
+
+    #[test]
+    fn migrate() {
+        let v1 = "title\talbum\tartist\t1\t1\tpath\t1\nbroken\n";
+        let (songs, errors) = deserialize_database(v1).unwrap();
+        assert_eq!(songs[0].title, "title");
+        assert_eq!(songs[0].year, 0);
+        assert_eq!(errors.len(), 1);
+        assert!(is_outdated(v1));
+
+        //Paths weren't encoded before version 4.
+        let v1 = "title\talbum\tartist\t1\t1\t100% music\t1\n";
+        let (songs, _) = deserialize_database(v1).unwrap();
+        assert_eq!(songs[0].path, Path::new("100% music"));
+
+        let current = serialize_database(&[Song::example()]);
+        assert!(!is_outdated(&current));
+        let (songs, errors) = deserialize_database(&current).unwrap();
+        assert_eq!(songs, vec![Song::example()]);
+        assert!(errors.is_empty());
     }
 
     #[test]
@@ -337,17 +1917,13 @@ mod tests {
 
     #[test]
     fn database() {
-        let handle = create("D:\\OneDrive\\Music");
+        let handle = create(&[PathBuf::from("D:\\OneDrive\\Music")]);
 
         while !handle.is_finished() {
             thread::sleep(Duration::from_millis(1));
         }
         handle.join().unwrap();
         let bytes = fs::read(database_path()).unwrap();
-        let db: Result<Vec<Song>, Box<dyn Error>> = unsafe { from_utf8_unchecked(&bytes) }
-            .lines()
-            .map(Song::deserialize)
-            .collect();
-        let _ = db.unwrap();
+        let _ = deserialize_database(unsafe { from_utf8_unchecked(&bytes) }).unwrap();
     }
 }
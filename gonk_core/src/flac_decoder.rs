@@ -1,25 +1,32 @@
-use crate::{db::UNKNOWN_ARTIST, Song};
+use crate::{
+    comments,
+    cover::Picture,
+    db::SongId,
+    db::{ScanError, UNKNOWN_ARTIST},
+    is_set, number, year, Song,
+};
 use std::{
     collections::HashMap,
     error::Error,
     fs::File,
-    io::{BufReader, Read},
+    io::{self, BufReader, Read},
     path::Path,
     str::from_utf8_unchecked,
+    time::Duration,
 };
 
 #[inline]
-pub fn u24_be(reader: &mut BufReader<File>) -> u32 {
+pub fn u24_be(reader: &mut BufReader<File>) -> io::Result<u32> {
     let mut triple = [0; 4];
-    reader.read_exact(&mut triple[0..3]).unwrap();
-    u32::from_be_bytes(triple) >> 8
+    reader.read_exact(&mut triple[0..3])?;
+    Ok(u32::from_be_bytes(triple) >> 8)
 }
 
 #[inline]
-pub fn u32_le(reader: &mut BufReader<File>) -> u32 {
+pub fn u32_le(reader: &mut BufReader<File>) -> io::Result<u32> {
     let mut buffer = [0; 4];
-    reader.read_exact(&mut buffer).unwrap();
-    u32::from_le_bytes(buffer)
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
 }
 
 pub fn read_metadata_old<P: AsRef<Path>>(
@@ -46,23 +53,23 @@ pub fn read_metadata_old<P: AsRef<Path>>(
 
         // The next 7 bits of the header indicates the block type.
         let block_type = flag[0] & 0x7f;
-        let block_len = u24_be(&mut reader);
+        let block_len = u24_be(&mut reader)?;
 
         //VorbisComment https://www.xiph.org/vorbis/doc/v-comment.html
         if block_type == 4 {
-            let vendor_length = u32_le(&mut reader);
+            let vendor_length = u32_le(&mut reader)?;
             reader.seek_relative(vendor_length as i64)?;
 
-            let comment_list_length = u32_le(&mut reader);
+            let comment_list_length = u32_le(&mut reader)?;
             for _ in 0..comment_list_length {
-                let length = u32_le(&mut reader) as usize;
+                let length = u32_le(&mut reader)? as usize;
                 let mut buffer = vec![0; length as usize];
                 reader.read_exact(&mut buffer)?;
 
-                let tag = core::str::from_utf8(&buffer).unwrap();
+                let tag = String::from_utf8_lossy(&buffer);
                 let (k, v) = match tag.split_once('=') {
                     Some((left, right)) => (left, right),
-                    None => (tag, ""),
+                    None => (tag.as_ref(), ""),
                 };
 
                 tags.insert(k.to_ascii_uppercase(), v.to_string());
@@ -82,7 +89,362 @@ pub fn read_metadata_old<P: AsRef<Path>>(
     Err("Could not parse metadata.")?
 }
 
+///https://xiph.org/flac/format.html#metadata_block_streaminfo
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub min_frame_size: u32,
+    pub max_frame_size: u32,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+    ///Zero if unknown.
+    pub total_samples: u64,
+    pub md5: [u8; 16],
+}
+
+impl StreamInfo {
+    pub fn parse(block: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if block.len() < 34 {
+            return Err("Invalid STREAMINFO block.")?;
+        }
+
+        //Sample rate (20 bits), channels (3 bits), bits per sample (5 bits), total samples (36 bits).
+        let packed = u64::from_be_bytes(block[10..18].try_into()?);
+
+        Ok(Self {
+            min_block_size: u16::from_be_bytes([block[0], block[1]]),
+            max_block_size: u16::from_be_bytes([block[2], block[3]]),
+            min_frame_size: u32::from_be_bytes([0, block[4], block[5], block[6]]),
+            max_frame_size: u32::from_be_bytes([0, block[7], block[8], block[9]]),
+            sample_rate: (packed >> 44) as u32,
+            channels: ((packed >> 41) & 0x7) as u8 + 1,
+            bits_per_sample: ((packed >> 36) & 0x1f) as u8 + 1,
+            total_samples: packed & 0xf_ffff_ffff,
+            md5: block[18..34].try_into()?,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        if self.sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.total_samples as f64 / self.sample_rate as f64)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CueIndex {
+    ///Offset in samples relative to the track offset.
+    pub offset: u64,
+    pub number: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CueTrack {
+    ///Offset in samples relative to the beginning of the stream.
+    pub offset: u64,
+    pub number: u8,
+    pub isrc: String,
+    pub is_audio: bool,
+    pub pre_emphasis: bool,
+    pub indices: Vec<CueIndex>,
+}
+
+impl CueTrack {
+    ///The lead-out track marks the end of the stream.
+    pub fn is_lead_out(&self) -> bool {
+        self.number == 170 || self.number == 255
+    }
+
+    ///Sample where the track starts, skipping the pregap (index 0) if there is one.
+    ///
+    ///`None` if the offsets overflow.
+    pub fn start(&self) -> Option<u64> {
+        let index = self
+            .indices
+            .iter()
+            .find(|index| index.number == 1)
+            .or(self.indices.first());
+
+        match index {
+            Some(index) => self.offset.checked_add(index.offset),
+            None => Some(self.offset),
+        }
+    }
+}
+
+///A track from an embedded cue sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueRange {
+    pub number: u8,
+    ///First sample of the track.
+    pub start: u64,
+    ///One past the last sample of the track.
+    pub end: u64,
+}
+
+///https://xiph.org/flac/format.html#metadata_block_cuesheet
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CueSheet {
+    pub catalog: String,
+    pub lead_in: u64,
+    pub is_cd: bool,
+    pub tracks: Vec<CueTrack>,
+}
+
+impl CueSheet {
+    pub fn parse(block: &[u8]) -> Result<Self, Box<dyn Error>> {
+        const HEADER: usize = 128 + 8 + 259 + 1;
+        const TRACK: usize = 8 + 1 + 12 + 14 + 1;
+        const INDEX: usize = 8 + 1 + 3;
+
+        if block.len() < HEADER {
+            return Err("Invalid CUESHEET block.")?;
+        }
+
+        let catalog = ascii(&block[..128]);
+        let lead_in = u64::from_be_bytes(block[128..136].try_into()?);
+        let is_cd = block[136] & 0x80 == 0x80;
+        let track_count = block[395];
+
+        let mut tracks = Vec::with_capacity(track_count as usize);
+        let mut i = HEADER;
+
+        for _ in 0..track_count {
+            let track = block.get(i..i + TRACK).ok_or("Truncated CUESHEET track.")?;
+            let index_count = track[TRACK - 1] as usize;
+            i += TRACK;
+
+            let mut indices = Vec::with_capacity(index_count);
+            for _ in 0..index_count {
+                let index = block.get(i..i + INDEX).ok_or("Truncated CUESHEET index.")?;
+                indices.push(CueIndex {
+                    offset: u64::from_be_bytes(index[..8].try_into()?),
+                    number: index[8],
+                });
+                i += INDEX;
+            }
+
+            let track = CueTrack {
+                offset: u64::from_be_bytes(track[..8].try_into()?),
+                number: track[8],
+                isrc: ascii(&track[9..21]),
+                is_audio: track[21] & 0x80 == 0,
+                pre_emphasis: track[21] & 0x40 == 0x40,
+                indices,
+            };
+            if track.start().is_none() {
+                return Err("Invalid CUESHEET track offset.")?;
+            }
+            tracks.push(track);
+        }
+
+        Ok(Self {
+            catalog,
+            lead_in,
+            is_cd,
+            tracks,
+        })
+    }
+
+    ///Split the stream into the audio tracks described by the cue sheet.
+    ///
+    ///Each track ends where the next one starts, the last track ends at the lead-out.
+    ///Tracks that would end before they start are skipped.
+    pub fn ranges(&self) -> Vec<CueRange> {
+        let mut ranges = Vec::new();
+
+        for (i, track) in self.tracks.iter().enumerate() {
+            if track.is_lead_out() || !track.is_audio {
+                continue;
+            }
+
+            let Some(next) = self.tracks.get(i + 1) else {
+                break;
+            };
+
+            let end = if next.is_lead_out() {
+                Some(next.offset)
+            } else {
+                next.start()
+            };
+            let (Some(start), Some(end)) = (track.start(), end) else {
+                continue;
+            };
+            if end < start {
+                continue;
+            }
+
+            ranges.push(CueRange {
+                number: track.number,
+                start,
+                end,
+            });
+        }
+
+        ranges
+    }
+}
+
+///Null padded ASCII string.
+fn ascii(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+///Map a vorbis comment onto the song.
+///
+///Shared by the FLAC and Ogg readers.
+pub fn vorbis_comment(song: &mut Song, key: &str, value: &str) {
+    match key.to_ascii_lowercase().as_str() {
+        "albumartist" | "album artist" => {
+            song.artist = value.to_string();
+            song.album_artist = value.to_string();
+        }
+        "compilation" => song.compilation = is_set(value),
+        "artistsort" => song.artist_sort = value.to_string(),
+        "albumartistsort" => song.album_artist_sort = value.to_string(),
+        "albumsort" => song.album_sort = value.to_string(),
+        "artist" => {
+            if song.artist == UNKNOWN_ARTIST {
+                song.artist = value.to_string();
+            }
+            song.track_artists.push(value.to_string());
+        }
+        "title" => song.title = value.to_string(),
+        "album" => song.album = value.to_string(),
+        "tracknumber" => {
+            let (n, total) = number(value);
+            song.track_number = n.unwrap_or(1);
+            if let Some(total) = total {
+                song.track_total = total;
+            }
+        }
+        "discnumber" => {
+            let (n, total) = number(value);
+            song.disc_number = n.unwrap_or(1);
+            if let Some(total) = total {
+                song.disc_total = total;
+            }
+        }
+        "tracktotal" | "totaltracks" => {
+            if let (Some(total), _) = number(value) {
+                song.track_total = total;
+            }
+        }
+        "disctotal" | "totaldiscs" => {
+            if let (Some(total), _) = number(value) {
+                song.disc_total = total;
+            }
+        }
+        "replaygain_track_gain" => {
+            //Remove the trailing " dB" from "-5.39 dB".
+            if let Some(slice) = value.get(..value.len().saturating_sub(3)) {
+                if let Ok(db) = slice.parse::<f32>() {
+                    song.gain = 10.0f32.powf(db / 20.0);
+                }
+            }
+        }
+        "date" | "year" if song.year == 0 => song.year = year(value),
+        //Fields can be repeated for each value.
+        "genre" => song.genres.push(value.to_string()),
+        "composer" => song.composers.push(value.to_string()),
+        "lyrics" | "unsyncedlyrics" => song.lyrics = value.to_string(),
+        "comment" | "description" if song.comment.is_empty() => song.comment = value.to_string(),
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flac {
+    pub song: Song,
+    pub stream_info: StreamInfo,
+    pub cue_sheet: Option<CueSheet>,
+    ///Why the CUESHEET block was ignored.
+    pub cue_error: Option<String>,
+    ///Text cue sheet from the CUESHEET comment, it has the track titles the block doesn't.
+    pub cue_text: Option<String>,
+}
+
+impl Flac {
+    ///A song for each audio track of the cue sheet, empty if there are less than two.
+    ///
+    ///`song` is the whole file, each track keeps its tags.
+    pub fn tracks(&self, song: &Song) -> Vec<Song> {
+        let ranges = match &self.cue_sheet {
+            Some(cue_sheet) => cue_sheet.ranges(),
+            None => return Vec::new(),
+        };
+        let sample_rate = self.stream_info.sample_rate as f64;
+        if ranges.len() < 2 || sample_rate == 0.0 {
+            return Vec::new();
+        }
+
+        let titles = cue_titles(self.cue_text.as_deref().unwrap_or_default());
+        ranges
+            .iter()
+            .map(|range| {
+                let mut track = song.clone();
+                track.track_number = range.number as u16;
+                track.id = SongId::track(&song.path, track.track_number);
+                track.track_total = ranges.len() as u16;
+                track.start = (range.start as f64 / sample_rate) as f32;
+                track.end = (range.end as f64 / sample_rate) as f32;
+                track.duration = track.end - track.start;
+                //Lyrics are timed for the whole file.
+                track.lyrics = String::new();
+
+                match titles.get(&range.number) {
+                    Some((title, performer)) => {
+                        if !title.is_empty() {
+                            track.title = title.clone();
+                        }
+                        if !performer.is_empty() {
+                            track.track_artists = vec![performer.clone()];
+                        }
+                    }
+                    None => track.title = format!("Track {:02}", range.number),
+                }
+                track
+            })
+            .collect()
+    }
+}
+
+///The title and performer of each track in a text cue sheet.
+///
+///https://wiki.hydrogenaud.io/index.php?title=Cue_sheet
+fn cue_titles(text: &str) -> HashMap<u8, (String, String)> {
+    let mut titles: HashMap<u8, (String, String)> = HashMap::new();
+    let mut track = None;
+
+    for line in text.lines() {
+        let (command, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let value = || rest.trim().trim_matches('"').to_string();
+
+        match (command.to_ascii_uppercase().as_str(), track) {
+            ("TRACK", _) => {
+                track = rest.split_whitespace().next().and_then(|n| n.parse().ok());
+                if let Some(track) = track {
+                    titles.entry(track).or_default();
+                }
+            }
+            ("TITLE", Some(track)) => titles.entry(track).or_default().0 = value(),
+            ("PERFORMER", Some(track)) => titles.entry(track).or_default().1 = value(),
+            _ => {}
+        }
+    }
+
+    titles
+}
+
 pub fn read_metadata<P: AsRef<Path>>(path: P) -> Result<Song, Box<dyn Error>> {
+    read_flac(path).map(|flac| flac.song)
+}
+
+pub fn read_flac<P: AsRef<Path>>(path: P) -> Result<Flac, Box<dyn Error>> {
     let file = File::open(&path)?;
     let mut reader = BufReader::new(file);
 
@@ -90,12 +452,18 @@ pub fn read_metadata<P: AsRef<Path>>(path: P) -> Result<Song, Box<dyn Error>> {
     reader.read_exact(&mut flac)?;
 
     if unsafe { from_utf8_unchecked(&flac) } != "fLaC" {
-        Err("File is not FLAC.")?;
+        return Err(ScanError::UnsupportedFormat(
+            "File is not FLAC.".to_string(),
+        ))?;
     }
 
     let mut song: Song = Song::default();
-    song.path = path.as_ref().to_string_lossy().to_string();
+    song.path = path.as_ref().to_path_buf();
 
+    let mut stream_info = None;
+    let mut cue_sheet = None;
+    let mut cue_error = None;
+    let mut cue_text = None;
     let mut flag = [0; 1];
 
     loop {
@@ -106,56 +474,95 @@ pub fn read_metadata<P: AsRef<Path>>(path: P) -> Result<Song, Box<dyn Error>> {
 
         // The next 7 bits of the header indicates the block type.
         let block_type = flag[0] & 0x7f;
-        let block_len = u24_be(&mut reader);
-
-        //VorbisComment https://www.xiph.org/vorbis/doc/v-comment.html
-        if block_type == 4 {
-            let vendor_length = u32_le(&mut reader);
-            reader.seek_relative(vendor_length as i64)?;
-
-            let comment_list_length = u32_le(&mut reader);
-            for _ in 0..comment_list_length {
-                let length = u32_le(&mut reader) as usize;
-                let mut buffer = vec![0; length as usize];
-                reader.read_exact(&mut buffer)?;
-
-                let tag = core::str::from_utf8(&buffer).unwrap();
-                let (k, v) = match tag.split_once('=') {
-                    Some((left, right)) => (left, right),
-                    None => (tag, ""),
-                };
+        let block_len = u24_be(&mut reader)?;
 
-                match k.to_ascii_lowercase().as_str() {
-                    "albumartist" => song.artist = v.to_string(),
-                    "artist" if song.artist == UNKNOWN_ARTIST => song.artist = v.to_string(),
-                    "title" => song.title = v.to_string(),
-                    "album" => song.album = v.to_string(),
-                    "tracknumber" => song.track_number = v.parse().unwrap_or(1),
-                    "discnumber" => song.disc_number = v.parse().unwrap_or(1),
-                    "replaygain_track_gain" => {
-                        //Remove the trailing " dB" from "-5.39 dB".
-                        if let Some(slice) = v.get(..v.len() - 3) {
-                            if let Ok(db) = slice.parse::<f32>() {
-                                song.gain = 10.0f32.powf(db / 20.0);
-                            }
-                        }
+        match block_type {
+            0 => {
+                let mut block = vec![0; block_len as usize];
+                reader.read_exact(&mut block)?;
+                stream_info = Some(StreamInfo::parse(&block)?);
+            }
+            //VorbisComment https://www.xiph.org/vorbis/doc/v-comment.html
+            4 => {
+                //Lengths inside the block are checked against the block, so corrupt files can't over-allocate.
+                let mut block = vec![0; block_len as usize];
+                reader.read_exact(&mut block)?;
+                comments(&block, |k, v| {
+                    if k.eq_ignore_ascii_case("cuesheet") {
+                        cue_text = Some(v.to_string());
                     }
-                    _ => {}
+                    vorbis_comment(&mut song, k, v);
+                })?;
+            }
+            //A broken cue sheet shouldn't stop the song from being added.
+            5 => {
+                let mut block = vec![0; block_len as usize];
+                reader.read_exact(&mut block)?;
+                match CueSheet::parse(&block) {
+                    Ok(cue) => cue_sheet = Some(cue),
+                    Err(err) => cue_error = Some(err.to_string()),
                 }
             }
+            //Pictures are only read when a cover is needed, see `read_flac_pictures`.
+            _ => reader.seek_relative(block_len as i64)?,
+        }
 
-            return Ok(song);
+        // Exit when the last header is read.
+        if is_last {
+            break;
         }
+    }
 
-        reader.seek_relative(block_len as i64)?;
+    let stream_info = stream_info.ok_or("Missing STREAMINFO block.")?;
+    song.sample_rate = stream_info.sample_rate;
+    song.channels = stream_info.channels;
+    song.bit_depth = stream_info.bits_per_sample;
+    song.duration = stream_info.duration().as_secs_f32();
+
+    Ok(Flac {
+        song,
+        stream_info,
+        cue_sheet,
+        cue_error,
+        cue_text,
+    })
+}
+
+///Every PICTURE block in the file.
+pub fn read_flac_pictures<P: AsRef<Path>>(path: P) -> Result<Vec<Picture>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut flac = [0; 4];
+    reader.read_exact(&mut flac)?;
+    if &flac != b"fLaC" {
+        return Err(ScanError::UnsupportedFormat(
+            "File is not FLAC.".to_string(),
+        ))?;
+    }
+
+    let mut pictures = Vec::new();
+    let mut flag = [0; 1];
+
+    loop {
+        reader.read_exact(&mut flag)?;
+        let is_last = (flag[0] & 0x80) == 0x80;
+        let block_type = flag[0] & 0x7f;
+        let block_len = u24_be(&mut reader)?;
+
+        if block_type == 6 {
+            let mut block = vec![0; block_len as usize];
+            reader.read_exact(&mut block)?;
+            pictures.push(Picture::flac(&block)?);
+        } else {
+            reader.seek_relative(block_len as i64)?;
+        }
 
-        // Exit when the last header is read.
         if is_last {
             break;
         }
     }
 
-    Err("Could not parse metadata.")?
+    Ok(pictures)
 }
 
 #[cfg(test)]
@@ -166,25 +573,226 @@ mod tests {
     fn test() {
         const PATH: &str = "D:\\OneDrive\\Music";
 
-        let paths: Vec<winwalk::DirEntry> = winwalk::walkdir(PATH, 0)
-            .into_iter()
-            .flatten()
-            .filter(|entry| match entry.extension() {
-                Some(ex) => {
-                    matches!(ex.to_str(), Some("flac"))
-                }
-                None => false,
-            })
-            .collect();
+        let paths = walk::walk(PATH, &["flac"]);
 
         let songs: Vec<Result<Song, String>> = paths
             .iter()
-            .map(|file| {
-                read_metadata(&file.path)
-                    .map_err(|err| format!("Error: ({err}) @ {}", file.path.to_string()))
+            .map(|path| {
+                read_metadata(path).map_err(|err| format!("Error: ({err}) @ {}", path.display()))
             })
             .collect();
 
         dbg!(&songs[0].as_ref().unwrap());
     }
+
+    #[test]
+    fn stream_info() {
+        let mut block = [0; 34];
+        block[0..2].copy_from_slice(&4096u16.to_be_bytes());
+        block[2..4].copy_from_slice(&4096u16.to_be_bytes());
+        //44100Hz, 2 channels, 16 bits, 441000 samples.
+        let packed: u64 = (44100 << 44) | (1 << 41) | (15 << 36) | 441000;
+        block[10..18].copy_from_slice(&packed.to_be_bytes());
+
+        let info = StreamInfo::parse(&block).unwrap();
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.bits_per_sample, 16);
+        assert_eq!(info.total_samples, 441000);
+        assert_eq!(info.duration().as_secs(), 10);
+    }
+
+    fn track(block: &mut Vec<u8>, offset: u64, number: u8, indices: &[(u64, u8)]) {
+        block.extend(offset.to_be_bytes());
+        block.push(number);
+        block.extend([0; 12]);
+        block.extend([0; 14]);
+        block.push(indices.len() as u8);
+        for (offset, number) in indices {
+            block.extend(offset.to_be_bytes());
+            block.push(*number);
+            block.extend([0; 3]);
+        }
+    }
+
+    #[test]
+    fn cue_sheet() {
+        let mut block = vec![0; 128 + 8 + 259 + 1];
+        block[128..136].copy_from_slice(&88200u64.to_be_bytes());
+        block[136] = 0x80;
+        block[395] = 3;
+        track(&mut block, 0, 1, &[(0, 1)]);
+        track(&mut block, 1000, 2, &[(0, 0), (200, 1)]);
+        track(&mut block, 5000, 170, &[]);
+
+        let cue = CueSheet::parse(&block).unwrap();
+        assert!(cue.is_cd);
+        assert_eq!(cue.lead_in, 88200);
+        assert_eq!(cue.tracks.len(), 3);
+        assert_eq!(
+            cue.ranges(),
+            vec![
+                CueRange {
+                    number: 1,
+                    start: 0,
+                    end: 1200
+                },
+                CueRange {
+                    number: 2,
+                    start: 1200,
+                    end: 5000
+                }
+            ]
+        );
+
+        //Tracks that end before they start.
+        let mut block = vec![0; 128 + 8 + 259 + 1];
+        block[395] = 3;
+        track(&mut block, 0, 1, &[(0, 1)]);
+        track(&mut block, 5000, 2, &[(0, 1)]);
+        track(&mut block, 1000, 170, &[]);
+        let ranges = CueSheet::parse(&block).unwrap().ranges();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].number, 1);
+
+        //Offsets that overflow.
+        let mut block = vec![0; 128 + 8 + 259 + 1];
+        block[395] = 1;
+        track(&mut block, u64::MAX, 1, &[(1, 1)]);
+        assert!(CueSheet::parse(&block).is_err());
+    }
+
+    #[test]
+    fn split() {
+        use crate::db::{read_songs, ScanError, SongId};
+
+        let dir = std::env::temp_dir().join("gonk_flac_split");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let header = |kind: u8, len: usize| [kind, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+
+        //44100Hz, 2 channels, 16 bits, 441000 samples.
+        let mut info = [0; 34];
+        let packed: u64 = (44100 << 44) | (1 << 41) | (15 << 36) | 441000;
+        info[10..18].copy_from_slice(&packed.to_be_bytes());
+        let stream_info = [&header(0, 34)[..], &info].concat();
+
+        let comment = "CUESHEET=TITLE \"Album\"\nTRACK 01 AUDIO\n  TITLE \"First\"\nTRACK 02 AUDIO\n  TITLE \"Second\"\n  PERFORMER \"Guest\"";
+        let mut comments = Vec::new();
+        comments.extend(0u32.to_le_bytes());
+        comments.extend(1u32.to_le_bytes());
+        comments.extend((comment.len() as u32).to_le_bytes());
+        comments.extend(comment.as_bytes());
+        let comments = [&header(4, comments.len())[..], &comments].concat();
+
+        let mut cue = vec![0; 128 + 8 + 259 + 1];
+        cue[395] = 3;
+        track(&mut cue, 0, 1, &[(0, 1)]);
+        track(&mut cue, 220500, 2, &[(0, 1)]);
+        track(&mut cue, 441000, 170, &[]);
+
+        let path = dir.join("image.flac");
+        let file = [
+            &b"fLaC"[..],
+            &stream_info,
+            &comments,
+            &header(0x85, cue.len()),
+            &cue,
+        ]
+        .concat();
+        std::fs::write(&path, file).unwrap();
+
+        let (songs, warning) = read_songs(&path).unwrap();
+        assert_eq!(warning, None);
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].title, "First");
+        assert_eq!(songs[1].title, "Second");
+        assert_eq!(songs[1].track_artists, ["Guest"]);
+        assert_eq!((songs[0].start, songs[0].end), (0.0, 5.0));
+        assert_eq!((songs[1].start, songs[1].end), (5.0, 10.0));
+        assert_eq!(songs[1].track_number, 2);
+        assert_eq!(songs[1].id, SongId::track(&path, 2));
+        assert_ne!(songs[0].id, songs[1].id);
+
+        //A broken cue sheet is ignored and the file is added as one song.
+        let path = dir.join("broken.flac");
+        let file = [
+            &b"fLaC"[..],
+            &stream_info,
+            &comments,
+            &header(0x85, 10),
+            &[0; 10],
+        ]
+        .concat();
+        std::fs::write(&path, file).unwrap();
+
+        let (songs, warning) = read_songs(&path).unwrap();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[0].end, 0.0);
+        assert!(matches!(warning, Some(ScanError::InvalidCueSheet(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        use crate::db::{check_tags, ScanError};
+
+        let dir = std::env::temp_dir().join("gonk_flac_errors");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut stream_info = vec![0x00, 0, 0, 34];
+        stream_info.extend([0; 34]);
+
+        //Vendor string, one comment that isn't valid UTF-8 and a comment count that's too large.
+        let mut comments = Vec::new();
+        comments.extend(0u32.to_le_bytes());
+        comments.extend(1u32.to_le_bytes());
+        comments.extend(8u32.to_le_bytes());
+        comments.extend(b"TITLE=\xff\xfe");
+        let mut comment_block = vec![0x84, 0, 0, comments.len() as u8];
+        comment_block.extend(&comments);
+
+        let valid = [&b"fLaC"[..], &stream_info, &comment_block].concat();
+        let path = dir.join("valid.flac");
+        std::fs::write(&path, &valid).unwrap();
+        let song = read_metadata(&path).unwrap();
+        assert_eq!(
+            check_tags(&song),
+            Some(ScanError::BadTagEncoding(vec!["title"]))
+        );
+
+        let path = dir.join("truncated.flac");
+        std::fs::write(&path, &valid[..20]).unwrap();
+        assert!(matches!(
+            Song::try_from(path.as_path()),
+            Err(ScanError::CorruptHeader(_))
+        ));
+
+        comments[4..8].copy_from_slice(&1000u32.to_le_bytes());
+        let mut comment_block = vec![0x84, 0, 0, comments.len() as u8];
+        comment_block.extend(&comments);
+        let path = dir.join("comments.flac");
+        std::fs::write(&path, [&b"fLaC"[..], &stream_info, &comment_block].concat()).unwrap();
+        assert!(matches!(
+            Song::try_from(path.as_path()),
+            Err(ScanError::CorruptHeader(_))
+        ));
+
+        let path = dir.join("mp3.flac");
+        std::fs::write(&path, b"ID3\x03").unwrap();
+        assert!(matches!(
+            Song::try_from(path.as_path()),
+            Err(ScanError::UnsupportedFormat(_))
+        ));
+
+        assert!(matches!(
+            Song::try_from(dir.join("missing.flac").as_path()),
+            Err(ScanError::Unreadable(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
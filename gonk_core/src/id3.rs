@@ -0,0 +1,855 @@
+//! ID3v2.3/ID3v2.4 tag reader with an ID3v1 fallback.
+//!
+//! https://id3.org/id3v2.3.0
+//! https://id3.org/id3v2.4.0-structure
+//! https://id3.org/id3v2.4.0-frames
+use crate::{
+    cover::Picture,
+    db::{ScanError, UNKNOWN_ARTIST},
+    is_set, lyrics, number, year, Song,
+};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+pub fn read_id3<P: AsRef<Path>>(path: P) -> Result<Song, Box<dyn Error>> {
+    let mut file = File::open(&path)?;
+    let file_size = file.metadata()?.len();
+
+    let mut song = Song::default();
+    song.path = path.as_ref().to_path_buf();
+
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+
+    if &header[..3] == b"ID3" {
+        let tag = read_tag(&mut file, &header)?;
+        let size = tag.len();
+        read_id3v2(&header, tag, &mut song)?;
+
+        //ID3v2.4 can have a 10 byte footer.
+        let footer = if header[5] & 0x10 == 0x10 { 10 } else { 0 };
+
+        //Missing stream properties aren't fatal.
+        let _ = read_mpeg(&mut file, 10 + size as u64 + footer, file_size, &mut song);
+        return Ok(song);
+    }
+
+    //ID3v1 lives in the last 128 bytes of the file.
+    if file_size < 128 {
+        return Err(ScanError::CorruptHeader(
+            "File is too small for an ID3v1 tag.".to_string(),
+        ))?;
+    }
+    let mut tag = [0; 128];
+    file.seek(SeekFrom::End(-128))?;
+    file.read_exact(&mut tag)?;
+    read_id3v1(&tag, &mut song)?;
+
+    let _ = read_mpeg(&mut file, 0, file_size - 128, &mut song);
+    Ok(song)
+}
+
+///Read the ID3v2 tag after its 10 byte header.
+///
+///The size comes from the file, so it's only trusted as far as the file goes.
+fn read_tag(file: &mut File, header: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let size = syncsafe(&header[6..10]) as u64;
+    let mut tag = Vec::new();
+    file.take(size).read_to_end(&mut tag)?;
+    if tag.len() as u64 != size {
+        return Err(ScanError::CorruptHeader(
+            "ID3v2 tag is larger than the file.".to_string(),
+        ))?;
+    }
+    Ok(tag)
+}
+
+///Read the stream properties from the first MPEG audio frame.
+///
+///The duration comes from the Xing/Info or VBRI header if there is one,
+///otherwise the stream is assumed to be constant bitrate.
+///
+///A frame only counts if the next one starts right after it, since tags and
+///pictures often contain bytes that look like a frame header.
+///
+///http://www.mp3-tech.org/programmer/frame_header.html
+pub fn read_mpeg(
+    file: &mut File,
+    offset: u64,
+    end: u64,
+    song: &mut Song,
+) -> Result<(), Box<dyn Error>> {
+    const BITRATES: [[u32; 15]; 5] = [
+        //MPEG 1 Layer 1, 2, 3
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        //MPEG 2/2.5 Layer 1 and Layer 2, 3
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ];
+    const SAMPLE_RATES: [[u32; 3]; 3] = [
+        [44100, 48000, 32000],
+        [22050, 24000, 16000],
+        [11025, 12000, 8000],
+    ];
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::new();
+    file.take(16 * 1024).read_to_end(&mut buffer)?;
+
+    for i in 0..buffer.len().saturating_sub(4) {
+        let header = u32::from_be_bytes(buffer[i..i + 4].try_into()?);
+
+        if header >> 21 != 0x7ff {
+            continue;
+        }
+
+        //0 = MPEG 2.5, 1 = reserved, 2 = MPEG 2, 3 = MPEG 1
+        let version = (header >> 19) & 0x3;
+        //1 = Layer 3, 2 = Layer 2, 3 = Layer 1
+        let layer = (header >> 17) & 0x3;
+        let bitrate_index = ((header >> 12) & 0xf) as usize;
+        let sample_rate_index = ((header >> 10) & 0x3) as usize;
+        let mono = (header >> 6) & 0x3 == 0x3;
+
+        if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+            continue;
+        }
+
+        if sample_rate_index == 3 {
+            continue;
+        }
+
+        let mpeg1 = version == 3;
+        let bitrate = match (mpeg1, layer) {
+            (true, 3) => BITRATES[0][bitrate_index],
+            (true, 2) => BITRATES[1][bitrate_index],
+            (true, _) => BITRATES[2][bitrate_index],
+            (false, 3) => BITRATES[3][bitrate_index],
+            (false, _) => BITRATES[4][bitrate_index],
+        };
+        let sample_rate = match version {
+            3 => SAMPLE_RATES[0][sample_rate_index],
+            2 => SAMPLE_RATES[1][sample_rate_index],
+            _ => SAMPLE_RATES[2][sample_rate_index],
+        };
+        let samples_per_frame = match layer {
+            3 => 384,
+            2 => 1152,
+            _ if mpeg1 => 1152,
+            _ => 576,
+        };
+
+        let padding = (header >> 9) & 0x1;
+        let len = match (mpeg1, layer) {
+            (_, 3) => (12 * bitrate * 1000 / sample_rate + padding) * 4,
+            (false, 1) => 72 * bitrate * 1000 / sample_rate + padding,
+            _ => 144 * bitrate * 1000 / sample_rate + padding,
+        } as usize;
+
+        //The last frame of the stream doesn't have one after it.
+        if offset + ((i + len) as u64) < end {
+            let Some(next) = buffer.get(i + len..i + len + 4) else {
+                continue;
+            };
+            let next = u32::from_be_bytes(next.try_into()?);
+            //Sync, version, layer and sample rate have to match.
+            if next & 0xfffe0c00 != header & 0xfffe0c00 {
+                continue;
+            }
+        }
+
+        song.sample_rate = sample_rate;
+        song.channels = if mono { 1 } else { 2 };
+
+        //The Xing header is stored after the side information.
+        let side_info = match (mpeg1, mono) {
+            (true, true) => 17,
+            (true, false) => 32,
+            (false, true) => 9,
+            (false, false) => 17,
+        };
+
+        let frame = &buffer[i..];
+        let xing = frame.get(4 + side_info..4 + side_info + 12);
+        let vbri = frame.get(36..36 + 18);
+
+        let frames = match (xing, vbri) {
+            (Some(xing), _) if &xing[..4] == b"Xing" || &xing[..4] == b"Info" => {
+                let flags = u32::from_be_bytes(xing[4..8].try_into()?);
+                (flags & 0x1 == 0x1)
+                    .then(|| u32::from_be_bytes([xing[8], xing[9], xing[10], xing[11]]))
+            }
+            (_, Some(vbri)) if &vbri[..4] == b"VBRI" => {
+                Some(u32::from_be_bytes(vbri[14..18].try_into()?))
+            }
+            _ => None,
+        };
+
+        if let Some(frames) = frames {
+            song.duration = frames as f32 * samples_per_frame as f32 / sample_rate as f32;
+        } else {
+            let audio = end.saturating_sub(offset + i as u64);
+            song.bitrate = bitrate;
+            song.duration = audio as f32 * 8.0 / (bitrate as f32 * 1000.0);
+        }
+
+        return Ok(());
+    }
+
+    Err("Could not find an MPEG frame.")?
+}
+
+///`header` is the 10 byte tag header and `tag` is everything after it.
+pub fn read_id3v2(header: &[u8], tag: Vec<u8>, song: &mut Song) -> Result<(), Box<dyn Error>> {
+    frames(header, tag, |id, data| match id {
+        b"TPE2" => {
+            song.artist = text(data);
+            song.album_artist = song.artist.clone();
+        }
+        //iTunes compilation flag.
+        b"TCMP" => song.compilation = is_set(&text(data)),
+        b"TSOP" => song.artist_sort = text(data),
+        b"TSO2" => song.album_artist_sort = text(data),
+        b"TSOA" => song.album_sort = text(data),
+        b"TPE1" => {
+            let artists = texts(data);
+            match artists.first() {
+                Some(artist) if song.artist == UNKNOWN_ARTIST => song.artist = artist.clone(),
+                _ => {}
+            }
+            song.track_artists = artists;
+        }
+        b"TALB" => song.album = text(data),
+        b"TIT2" => song.title = text(data),
+        b"TRCK" => {
+            let (n, total) = number(&text(data));
+            song.track_number = n.unwrap_or(1);
+            if let Some(total) = total {
+                song.track_total = total;
+            }
+        }
+        b"TPOS" => {
+            let (n, total) = number(&text(data));
+            song.disc_number = n.unwrap_or(1);
+            if let Some(total) = total {
+                song.disc_total = total;
+            }
+        }
+        b"TDRC" | b"TYER" | b"TDRL" if song.year == 0 => song.year = year(&text(data)),
+        b"TCON" => song.genres = texts(data).iter().map(|g| genre(g)).collect(),
+        b"TCOM" => song.composers = texts(data),
+        b"TLEN" if song.duration == 0.0 => {
+            //Length in milliseconds.
+            if let Ok(ms) = text(data).trim().parse::<f32>() {
+                song.duration = ms / 1000.0;
+            }
+        }
+        //Synced lyrics are preferred.
+        b"USLT" if song.lyrics.is_empty() => song.lyrics = language_text(data).1,
+        b"SYLT" => {
+            if let Some(lyrics) = synced_lyrics(data) {
+                song.lyrics = lyrics;
+            }
+        }
+        b"COMM" => {
+            //iTunes stores normalization and gapless data in comments with a description.
+            let (description, comment) = language_text(data);
+            if description.is_empty()
+                || (song.comment.is_empty() && !description.starts_with("iTun"))
+            {
+                song.comment = comment;
+            }
+        }
+        b"TXXX" => {
+            let (description, value) = user_text(data);
+            if description.eq_ignore_ascii_case("replaygain_track_gain") {
+                //Remove the trailing " dB" from "-5.39 dB".
+                let value = value.trim_end_matches("dB").trim();
+                if let Ok(db) = value.parse::<f32>() {
+                    song.gain = 10.0f32.powf(db / 20.0);
+                }
+            }
+        }
+        _ => {}
+    })
+}
+
+///Every APIC frame in the ID3v2 tag, ID3v1 doesn't have pictures.
+pub fn read_id3_pictures<P: AsRef<Path>>(path: P) -> Result<Vec<Picture>, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+
+    if &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+
+    let tag = read_tag(&mut file, &header)?;
+
+    let mut pictures = Vec::new();
+    frames(&header, tag, |id, data| {
+        if id == b"APIC" {
+            pictures.extend(picture(data));
+        }
+    })?;
+    Ok(pictures)
+}
+
+///Call `f` with the id and data of each frame, skipping compressed and encrypted frames.
+fn frames(
+    header: &[u8],
+    mut tag: Vec<u8>,
+    mut f: impl FnMut(&[u8], &[u8]),
+) -> Result<(), Box<dyn Error>> {
+    let version = header[3];
+    let flags = header[5];
+
+    if version != 3 && version != 4 {
+        return Err(ScanError::UnsupportedFormat(format!(
+            "Unsupported ID3v2.{version} tag."
+        )))?;
+    }
+
+    //ID3v2.3 unsynchronises the whole tag, ID3v2.4 does it per frame.
+    if version == 3 && flags & 0x80 == 0x80 {
+        tag = resync(&tag);
+    }
+
+    let mut i = 0;
+
+    //Skip the extended header.
+    if flags & 0x40 == 0x40 {
+        let size = tag.get(0..4).ok_or("Invalid extended header.")?;
+        i = if version == 3 {
+            u32::from_be_bytes(size.try_into()?) as usize + 4
+        } else {
+            syncsafe(size) as usize
+        };
+    }
+
+    while i + 10 <= tag.len() {
+        let id = &tag[i..i + 4];
+
+        //Reached the padding.
+        if id[0] == 0 {
+            break;
+        }
+
+        let size = if version == 3 {
+            u32::from_be_bytes(tag[i + 4..i + 8].try_into()?) as usize
+        } else {
+            syncsafe(&tag[i + 4..i + 8]) as usize
+        };
+        let format = tag[i + 9];
+        let start = i + 10;
+        let end = start + size;
+        i = end;
+
+        let Some(data) = tag.get(start..end) else {
+            break;
+        };
+
+        let (compressed, encrypted) = if version == 3 {
+            (format & 0x80 == 0x80, format & 0x40 == 0x40)
+        } else {
+            (format & 0x08 == 0x08, format & 0x04 == 0x04)
+        };
+
+        if compressed || encrypted {
+            continue;
+        }
+
+        let mut data = Cow::Borrowed(data);
+
+        if version == 3 && format & 0x20 == 0x20 {
+            //Grouping identity.
+            data = Cow::Owned(data.get(1..).unwrap_or_default().to_vec());
+        }
+
+        if version == 4 {
+            //Grouping identity, it comes before the data length indicator.
+            if format & 0x40 == 0x40 {
+                data = Cow::Owned(data.get(1..).unwrap_or_default().to_vec());
+            }
+            if format & 0x01 == 0x01 {
+                //Data length indicator.
+                data = Cow::Owned(data.get(4..).unwrap_or_default().to_vec());
+            }
+            if format & 0x02 == 0x02 {
+                data = Cow::Owned(resync(&data));
+            }
+        }
+
+        f(id, &data);
+    }
+
+    Ok(())
+}
+
+pub fn read_id3v1(tag: &[u8; 128], song: &mut Song) -> Result<(), Box<dyn Error>> {
+    if &tag[..3] != b"TAG" {
+        return Err("No ID3 tag.")?;
+    }
+
+    let field = |bytes: &[u8]| latin1(bytes).trim_end_matches([' ', '\0']).to_string();
+
+    let title = field(&tag[3..33]);
+    let artist = field(&tag[33..63]);
+    let album = field(&tag[63..93]);
+
+    if !title.is_empty() {
+        song.title = title;
+    }
+    if !artist.is_empty() {
+        song.artist = artist;
+    }
+    if !album.is_empty() {
+        song.album = album;
+    }
+
+    //ID3v1.1 stores the track number in the last byte of the comment.
+    if tag[125] == 0 && tag[126] != 0 {
+        song.track_number = tag[126] as u16;
+    }
+
+    song.year = year(&field(&tag[93..97]));
+
+    if let Some(genre) = GENRES.get(tag[127] as usize) {
+        song.genres = vec![genre.to_string()];
+    }
+
+    Ok(())
+}
+
+///ID3v1 genres, also referenced by number in TCON frames.
+const GENRES: [&str; 80] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "AlternRock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+///Resolve "(17)", "(17)Rock" and "17" to the genre name.
+fn genre(s: &str) -> String {
+    let s = s.trim();
+    let number = match s.strip_prefix('(') {
+        Some(rest) => match rest.split_once(')') {
+            Some((number, "")) => number,
+            Some((_, name)) => return name.to_string(),
+            None => s,
+        },
+        None => s,
+    };
+
+    match number.parse::<usize>().ok().and_then(|i| GENRES.get(i)) {
+        Some(genre) => genre.to_string(),
+        None => s.to_string(),
+    }
+}
+
+///Sizes in ID3v2 headers use 7 bits per byte.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |acc, byte| (acc << 7) | (*byte & 0x7f) as u32)
+}
+
+///Undo unsynchronisation by removing the zero byte inserted after every 0xFF.
+fn resync(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut previous = 0;
+    for &byte in bytes {
+        if !(previous == 0xff && byte == 0) {
+            out.push(byte);
+        }
+        previous = byte;
+    }
+    out
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| {
+        if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        }
+    });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn decode(encoding: u8, bytes: &[u8]) -> String {
+    let s = match encoding {
+        //UTF-16 with byte order mark.
+        1 => match bytes {
+            [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+            [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+            _ => utf16(bytes, false),
+        },
+        2 => utf16(bytes, true),
+        3 => String::from_utf8_lossy(bytes).to_string(),
+        _ => latin1(bytes),
+    };
+    s.trim_end_matches('\0').to_string()
+}
+
+///Text information frames. Only the first value of ID3v2.4 lists is used.
+fn text(data: &[u8]) -> String {
+    let Some((&encoding, rest)) = data.split_first() else {
+        return String::new();
+    };
+    let (value, _) = terminated(encoding, rest);
+    decode(encoding, value)
+}
+
+///Every value of a text information frame, ID3v2.4 separates them with null characters.
+fn texts(data: &[u8]) -> Vec<String> {
+    let Some((&encoding, mut rest)) = data.split_first() else {
+        return Vec::new();
+    };
+    let mut values = Vec::new();
+    while !rest.is_empty() {
+        let (value, next) = terminated(encoding, rest);
+        let value = decode(encoding, value);
+        if !value.is_empty() {
+            values.push(value);
+        }
+        rest = next;
+    }
+    values
+}
+
+///User defined text frames are `<encoding><description>\0<value>`.
+fn user_text(data: &[u8]) -> (String, String) {
+    let Some((&encoding, rest)) = data.split_first() else {
+        return (String::new(), String::new());
+    };
+    let (description, value) = terminated(encoding, rest);
+    let (value, _) = terminated(encoding, value);
+    (decode(encoding, description), decode(encoding, value))
+}
+
+///Lyrics and comments are `<encoding><language><description>\0<text>`.
+fn language_text(data: &[u8]) -> (String, String) {
+    let Some((&encoding, rest)) = data.split_first() else {
+        return (String::new(), String::new());
+    };
+    let (description, text) = terminated(encoding, rest.get(3..).unwrap_or_default());
+    (decode(encoding, description), decode(encoding, text))
+}
+
+///Synchronised lyrics are `<encoding><language><time format><type><description>\0`
+///followed by `<text>\0<time>` for each line or syllable. They're converted to LRC.
+///
+///Only millisecond times are supported, not MPEG frames.
+fn synced_lyrics(data: &[u8]) -> Option<String> {
+    let (&encoding, rest) = data.split_first()?;
+    if *rest.get(3)? != 2 {
+        return None;
+    }
+    let (_, mut rest) = terminated(encoding, rest.get(5..)?);
+
+    let mut entries = Vec::new();
+    while rest.len() > 4 {
+        let (text, next) = terminated(encoding, rest);
+        let time = u32::from_be_bytes(next.get(..4)?.try_into().ok()?);
+        entries.push((decode(encoding, text), Duration::from_millis(time as u64)));
+        rest = &next[4..];
+    }
+
+    //Syllables are joined into lines, which start with a newline.
+    let syllables = entries
+        .iter()
+        .skip(1)
+        .any(|(text, _)| text.starts_with(['\n', '\r']));
+
+    let mut lrc = String::new();
+    for (i, (text, time)) in entries.iter().enumerate() {
+        let time = lyrics::format_time(*time);
+        if i == 0 || !syllables || text.starts_with(['\n', '\r']) {
+            if i != 0 {
+                lrc.push('\n');
+            }
+            lrc.push_str(&format!("[{time}]"));
+        }
+        if syllables {
+            lrc.push_str(&format!("<{time}>"));
+        }
+        lrc.push_str(text.trim_start_matches(['\n', '\r']));
+    }
+
+    (!lrc.is_empty()).then_some(lrc)
+}
+
+///Attached pictures are `<encoding><mime type>\0<picture type><description>\0<data>`.
+fn picture(data: &[u8]) -> Option<Picture> {
+    let (&encoding, rest) = data.split_first()?;
+    let (mime, rest) = terminated(0, rest);
+    let (&kind, rest) = rest.split_first()?;
+    let (description, data) = terminated(encoding, rest);
+    Some(Picture {
+        kind: kind as u32,
+        mime: latin1(mime),
+        description: decode(encoding, description),
+        data: data.to_vec(),
+    })
+}
+
+///Split at the first null terminator, which is two bytes wide for UTF-16.
+fn terminated(encoding: u8, bytes: &[u8]) -> (&[u8], &[u8]) {
+    let position = if encoding == 1 || encoding == 2 {
+        bytes
+            .chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|i| (i * 2, i * 2 + 2))
+    } else {
+        bytes.iter().position(|b| *b == 0).map(|i| (i, i + 1))
+    };
+
+    match position {
+        Some((end, next)) => (&bytes[..end], &bytes[next..]),
+        None => (bytes, &[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend((data.len() as u32).to_be_bytes());
+        frame.extend([0, 0]);
+        frame.extend(data);
+        frame
+    }
+
+    #[test]
+    fn id3v23() {
+        let mut tag = Vec::new();
+        tag.extend(frame(b"TIT2", b"\x03Title"));
+        tag.extend(frame(b"TPE1", b"\x00Artist\x00Guest"));
+        tag.extend(frame(b"TALB", b"\x01\xff\xfeA\x00l\x00b\x00"));
+        tag.extend(frame(b"TRCK", b"\x003/12"));
+        tag.extend(frame(b"TPOS", b"\x00300/301"));
+        tag.extend(frame(b"TDRC", b"\x001997-05-21"));
+        tag.extend(frame(b"TCON", b"\x00(17)"));
+        tag.extend(frame(b"TXXX", b"\x00REPLAYGAIN_TRACK_GAIN\x00-6.00 dB"));
+        tag.extend(frame(b"USLT", b"\x03eng\x00First line\nSecond line"));
+        tag.extend(frame(b"COMM", b"\x00engiTunNORM\x00 000001"));
+        tag.extend(frame(b"COMM", b"\x00eng\x00Comment"));
+        tag.extend([0; 16]);
+
+        let mut song = Song::default();
+        read_id3v2(b"ID3\x03\x00\x00\x00\x00\x00\x00", tag, &mut song).unwrap();
+
+        assert_eq!(song.title, "Title");
+        assert_eq!(song.artist, "Artist");
+        assert_eq!(song.album, "Alb");
+        assert_eq!(song.track_number, 3);
+        assert_eq!(song.track_total, 12);
+        assert_eq!(song.disc_number, 300);
+        assert_eq!(song.disc_total, 301);
+        assert_eq!(song.track_artists, ["Artist", "Guest"]);
+        assert_eq!(song.year, 1997);
+        assert_eq!(song.genres, ["Rock"]);
+        assert!((song.gain - 0.501).abs() < 0.001);
+        assert_eq!(song.lyrics, "First line\nSecond line");
+        assert_eq!(song.comment, "Comment");
+    }
+
+    #[test]
+    fn id3v24_flags() {
+        //Grouping identity and a data length indicator.
+        let data = [&[0x01][..], &[0, 0, 0, 6], b"\x03Title"].concat();
+        let mut tag = b"TIT2".to_vec();
+        tag.extend((data.len() as u32).to_be_bytes());
+        tag.extend([0, 0x41]);
+        tag.extend(data);
+        tag.extend([0; 16]);
+
+        let mut song = Song::default();
+        read_id3v2(b"ID3\x04\x00\x00\x00\x00\x00\x00", tag, &mut song).unwrap();
+        assert_eq!(song.title, "Title");
+    }
+
+    #[test]
+    fn synced() {
+        let sylt = |entries: &[(&str, u32)]| {
+            let mut data = b"\x03eng\x02\x01\x00".to_vec();
+            for (text, time) in entries {
+                data.extend(text.as_bytes());
+                data.push(0);
+                data.extend(time.to_be_bytes());
+            }
+            synced_lyrics(&data).unwrap()
+        };
+
+        assert_eq!(
+            sylt(&[("First", 1_000), ("Second", 62_500)]),
+            "[00:01.00]First\n[01:02.50]Second"
+        );
+        assert_eq!(
+            sylt(&[("Word ", 1_000), ("by", 1_500), ("\nNext", 2_000)]),
+            "[00:01.00]<00:01.00>Word <00:01.50>by\n[00:02.00]<00:02.00>Next"
+        );
+        //MPEG frame times aren't supported.
+        assert!(synced_lyrics(b"\x03eng\x01\x01\x00a\x00\x00\x00\x00\x01").is_none());
+    }
+
+    #[test]
+    fn attached_picture() {
+        let picture = picture(b"\x01image/jpeg\x00\x03\xff\xfeC\x00\x00\x00\xff\xd8").unwrap();
+        assert_eq!(picture.mime, "image/jpeg");
+        assert_eq!(picture.kind, 3);
+        assert_eq!(picture.description, "C");
+        assert_eq!(picture.data, [0xff, 0xd8]);
+    }
+
+    #[test]
+    fn unsynchronisation() {
+        assert_eq!(
+            resync(&[0xff, 0x00, 0xe0, 0x00, 0xff, 0x00]),
+            [0xff, 0xe0, 0x00, 0xff]
+        );
+    }
+
+    #[test]
+    fn mpeg() {
+        //MPEG 1 Layer 3, 128kbps, 44100Hz, stereo.
+        let header = [0xff, 0xfb, 0x90, 0x00];
+        let mut frame = vec![0; 417];
+        frame[..4].copy_from_slice(&header);
+
+        //Bytes that look like a frame header without a frame after them.
+        let mut stream = vec![0xff, 0xf3, 0x18, 0xc4, 0x00, 0x00];
+        stream.extend(&frame);
+        stream.extend(&frame);
+
+        let path = std::env::temp_dir().join("gonk_mpeg.mp3");
+        std::fs::write(&path, &stream).unwrap();
+        let mut song = Song::default();
+        let mut file = File::open(&path).unwrap();
+        read_mpeg(&mut file, 0, stream.len() as u64, &mut song).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(song.sample_rate, 44100);
+        assert_eq!(song.bitrate, 128);
+        assert_eq!(song.channels, 2);
+    }
+
+    #[test]
+    fn id3v1() {
+        let mut tag = [0; 128];
+        tag[..3].copy_from_slice(b"TAG");
+        tag[3..8].copy_from_slice(b"Title");
+        tag[33..39].copy_from_slice(b"Artist");
+        tag[126] = 7;
+
+        let mut song = Song::default();
+        read_id3v1(&tag, &mut song).unwrap();
+
+        assert_eq!(song.title, "Title");
+        assert_eq!(song.artist, "Artist");
+        assert_eq!(song.album, crate::db::UNKNOWN_ALBUM);
+        assert_eq!(song.track_number, 7);
+    }
+}
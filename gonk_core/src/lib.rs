@@ -1,6 +1,7 @@
 #![allow(static_mut_refs)]
 //! The physical database is a file on disk that stores song information.
-//! This information includes the artist, album, title, disc number, track number, path and replay gain.
+//! This information includes the artist, album, title, disc number, track number, path, replay gain,
+//! year, genre, composer and stream properties like the duration and sample rate.
 //!
 //! The virtual database stores key value pairs.
 //! It is used for quering artists, albums and songs.
@@ -22,16 +23,29 @@ pub use crate::{
     playlist::Playlist,
 };
 pub use flac_decoder::*;
+pub use id3::*;
 pub use index::*;
+pub use ogg::*;
 
+pub mod collate;
+pub mod cover;
 pub mod db;
 pub mod flac_decoder;
+pub mod id3;
 pub mod index;
 pub mod log;
+pub mod lyrics;
+pub mod migrate;
+pub mod ogg;
 pub mod playlist;
+pub mod plays;
+pub mod query;
+pub mod search;
 pub mod settings;
 pub mod strsim;
 pub mod vdb;
+pub mod walk;
+pub mod watcher;
 
 ///Escape potentially problematic strings.
 pub fn escape(input: &str) -> Cow<str> {
@@ -42,13 +56,57 @@ pub fn escape(input: &str) -> Cow<str> {
     }
 }
 
+///Parse the year from dates like "1997", "1997-05-21" or "05/21/1997".
+fn year(date: &str) -> u16 {
+    date.split(|c: char| !c.is_ascii_digit())
+        .find(|part| part.len() == 4)
+        .and_then(|year| year.parse().ok())
+        .unwrap_or(0)
+}
+
+///Flags like COMPILATION are "1" when set.
+fn is_set(value: &str) -> bool {
+    matches!(value.trim(), "1" | "true")
+}
+
+///Parse track and disc numbers like "3" and "3/12" into the number and total.
+fn number(s: &str) -> (Option<u16>, Option<u16>) {
+    let (n, total) = match s.split_once('/') {
+        Some((n, total)) => (n, total.trim().parse().ok()),
+        None => (s, None),
+    };
+    (n.trim().parse().ok(), total)
+}
+
 static mut GONK: MaybeUninit<PathBuf> = MaybeUninit::uninit();
 static mut SETTINGS: MaybeUninit<PathBuf> = MaybeUninit::uninit();
 static mut DATABASE: MaybeUninit<PathBuf> = MaybeUninit::uninit();
 static mut ONCE: Once = Once::new();
 
+///The user's home folder, `%USERPROFILE%` on Windows and `$HOME` everywhere else.
 pub fn user_profile_directory() -> Option<String> {
-    env::var("USERPROFILE").ok()
+    if cfg!(windows) {
+        env::var("USERPROFILE").ok()
+    } else {
+        env::var("HOME").ok()
+    }
+}
+
+///Replace a leading `~` with the user's home folder.
+pub fn expand_home(path: &str) -> Cow<'_, str> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return Cow::Borrowed(path);
+    };
+
+    //`~user` isn't supported.
+    if !rest.is_empty() && !rest.starts_with(['/', '\\']) {
+        return Cow::Borrowed(path);
+    }
+
+    match user_profile_directory() {
+        Some(home) => Cow::Owned(format!("{home}{rest}")),
+        None => Cow::Borrowed(path),
+    }
 }
 
 #[inline(always)]
@@ -110,3 +168,26 @@ where
 
     fn deserialize(s: &str) -> Result<Self, Self::Error>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home() {
+        let home = user_profile_directory().unwrap();
+        assert_eq!(expand_home("~/Music"), format!("{home}/Music"));
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~user/Music"), "~user/Music");
+        assert_eq!(expand_home("Music/~"), "Music/~");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(number("3"), (Some(3), None));
+        assert_eq!(number(" 3 / 12"), (Some(3), Some(12)));
+        assert_eq!(number("300/"), (Some(300), None));
+        assert_eq!(number("/12"), (None, Some(12)));
+        assert_eq!(number("A1"), (None, None));
+    }
+}
@@ -0,0 +1,300 @@
+//! Lyrics
+//!
+//! Read from a `.lrc` or `.txt` file next to the song, or from the SYLT, USLT and LYRICS tags.
+//! Lyrics files are preferred since they're usually added on purpose.
+//!
+//! Synced lyrics use the LRC format:
+//!
+//! ```text
+//! [ar:Artist]
+//! [offset:+250]
+//! [00:12.30]First line
+//! [00:15.00][01:15.00]A line that's repeated
+//! [00:18.00]<00:18.00>Word <00:18.40>by <00:18.90>word
+//! ```
+use std::{fs, ops::Range, path::Path, time::Duration};
+
+///Checked in order, `song.flac` uses `song.lrc` then `song.txt`.
+pub const SIDECAR_EXTENSIONS: [&str; 2] = ["lrc", "txt"];
+
+///Read the lyrics file next to a song, if there is one.
+pub fn sidecar(path: &Path) -> Option<String> {
+    SIDECAR_EXTENSIONS.iter().find_map(|extension| {
+        let text = fs::read_to_string(path.with_extension(extension)).ok()?;
+        let text = text.trim_start_matches('\u{feff}').trim();
+        (!text.is_empty()).then(|| text.replace("\r\n", "\n"))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub time: Duration,
+    ///Byte range of the word in the line, including any space after it.
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    ///`None` for lyrics without timestamps.
+    pub time: Option<Duration>,
+    pub text: String,
+    ///Word timings from enhanced LRC, empty if the line doesn't have them.
+    pub words: Vec<Word>,
+}
+
+impl Line {
+    ///How much of the line has been sung, in bytes. Lines without word timings are sung all at once.
+    pub fn sung(&self, elapsed: Duration) -> usize {
+        if self.words.is_empty() {
+            return self.text.len();
+        }
+        self.words
+            .iter()
+            .take_while(|word| word.time <= elapsed)
+            .last()
+            .map_or(0, |word| word.range.end)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lyrics {
+    ///Sorted by time when the lyrics are synced.
+    pub lines: Vec<Line>,
+}
+
+///Parse `mm:ss`, `mm:ss.xx` or `mm:ss:xx`.
+pub fn parse_time(s: &str) -> Option<Duration> {
+    let (minutes, rest) = s.trim().split_once(':')?;
+    let (seconds, fraction) = rest.split_once(['.', ':']).unwrap_or((rest, ""));
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(minutes) || !digits(seconds) || !(fraction.is_empty() || digits(fraction)) {
+        return None;
+    }
+
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    //".5" is half a second and ".05" is 50 milliseconds.
+    let fraction = &fraction[..fraction.len().min(3)];
+    let millis: u64 = format!("{fraction:0<3}").parse().ok()?;
+    //Huge timestamps in broken files would overflow.
+    let millis = minutes
+        .checked_mul(60_000)?
+        .checked_add(seconds.checked_mul(1000)?)?
+        .checked_add(millis)?;
+    Some(Duration::from_millis(millis))
+}
+
+///Format a time as `mm:ss.xx`.
+pub fn format_time(time: Duration) -> String {
+    let centiseconds = time.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+///Split a line into its text and word timings.
+fn words(line: &str) -> (String, Vec<Word>) {
+    let mut text = String::with_capacity(line.len());
+    let mut words: Vec<Word> = Vec::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        match parse_time(&rest[start + 1..start + len]) {
+            Some(time) => {
+                if let Some(last) = words.last_mut() {
+                    last.range.end = text.len();
+                }
+                words.push(Word {
+                    time,
+                    range: text.len()..text.len(),
+                });
+            }
+            //Not a timestamp, like "<3".
+            None => text.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    text.push_str(rest);
+
+    let len = text.trim_end().len();
+    text.truncate(len);
+    if let Some(last) = words.last_mut() {
+        last.range.end = len;
+    }
+    for word in &mut words {
+        word.range.start = word.range.start.min(len);
+    }
+    (text, words)
+}
+
+impl Lyrics {
+    ///Parse LRC or plain text lyrics. Lines without timestamps are dropped from synced lyrics.
+    pub fn parse(text: &str) -> Self {
+        let mut synced = Vec::new();
+        let mut plain = Vec::new();
+        //In milliseconds, positive values show the lyrics sooner.
+        let mut offset: i64 = 0;
+
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+
+            while let Some(tag) = rest.strip_prefix('[') {
+                let Some((tag, after)) = tag.split_once(']') else {
+                    break;
+                };
+                if let Some(time) = parse_time(tag) {
+                    times.push(time);
+                } else if let Some(("offset", value)) = tag.split_once(':') {
+                    offset = value.trim().parse().unwrap_or(0);
+                }
+                rest = after.trim_start();
+            }
+
+            let (text, words) = words(rest);
+            if times.is_empty() {
+                if !text.is_empty() {
+                    plain.push(Line {
+                        time: None,
+                        text,
+                        words,
+                    });
+                }
+                continue;
+            }
+
+            for time in times {
+                synced.push(Line {
+                    time: Some(time),
+                    text: text.clone(),
+                    words: words.clone(),
+                });
+            }
+        }
+
+        if synced.is_empty() {
+            return Self { lines: plain };
+        }
+
+        let shift = |time: Duration| {
+            let millis = i64::try_from(time.as_millis()).unwrap_or(i64::MAX);
+            let millis = millis.saturating_sub(offset);
+            Duration::from_millis(millis.max(0) as u64)
+        };
+        for line in &mut synced {
+            line.time = line.time.map(shift);
+            for word in &mut line.words {
+                word.time = shift(word.time);
+            }
+        }
+        synced.sort_by_key(|line| line.time);
+
+        Self { lines: synced }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.lines.first().is_some_and(|line| line.time.is_some())
+    }
+
+    ///The line being sung, `None` before the first line or if the lyrics aren't synced.
+    pub fn current(&self, elapsed: Duration) -> Option<usize> {
+        if !self.is_synced() {
+            return None;
+        }
+        self.lines
+            .partition_point(|line| line.time <= Some(elapsed))
+            .checked_sub(1)
+    }
+}
+
+///Each line of the lyrics without tags or timestamps, empty lines are skipped.
+pub fn lines(lyrics: &str) -> impl Iterator<Item = String> {
+    Lyrics::parse(lyrics)
+        .lines
+        .into_iter()
+        .map(|line| line.text)
+        .filter(|line| !line.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(parse_time("01:02.5"), Some(ms(62_500)));
+        assert_eq!(parse_time("01:02.05"), Some(ms(62_050)));
+        assert_eq!(parse_time("01:02:345"), Some(ms(62_345)));
+        assert_eq!(parse_time("1:02"), Some(ms(62_000)));
+        assert_eq!(parse_time("ar:Artist"), None);
+        assert_eq!(parse_time("01:xx"), None);
+        assert_eq!(parse_time("99999999999999999:00"), None);
+        assert_eq!(parse_time("00:99999999999999999"), None);
+        assert_eq!(format_time(ms(62_345)), "01:02.34");
+    }
+
+    #[test]
+    fn lrc() {
+        let lyrics = Lyrics::parse(
+            "[ti:Title]\n[offset:+500]\n[00:20.00][00:05.00]Chorus\n[00:10.00]Verse\n\n[00:15.00]",
+        );
+        let lines: Vec<_> = lyrics
+            .lines
+            .iter()
+            .map(|line| (line.time.unwrap(), line.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (ms(4_500), "Chorus"),
+                (ms(9_500), "Verse"),
+                (ms(14_500), ""),
+                (ms(19_500), "Chorus")
+            ]
+        );
+
+        assert!(lyrics.is_synced());
+        assert_eq!(lyrics.current(ms(0)), None);
+        assert_eq!(lyrics.current(ms(9_500)), Some(1));
+        assert_eq!(lyrics.current(ms(60_000)), Some(3));
+
+        //Offsets too large to apply don't panic.
+        let lyrics = Lyrics::parse("[offset:-9223372036854775808]\n[00:01.00]Line");
+        assert_eq!(lyrics.lines[0].time, Some(ms(i64::MAX as u64)));
+    }
+
+    #[test]
+    fn enhanced() {
+        let lyrics = Lyrics::parse("[00:01.00]<00:01.00>Word <00:01.50>by <00:02.00>word <3");
+        let line = &lyrics.lines[0];
+        assert_eq!(line.text, "Word by word <3");
+        assert_eq!(line.words.len(), 3);
+        assert_eq!(line.words[1].range, 5..8);
+        assert_eq!(line.sung(ms(500)), 0);
+        assert_eq!(line.sung(ms(1_600)), 8);
+        assert_eq!(line.sung(ms(3_000)), line.text.len());
+    }
+
+    #[test]
+    fn plain() {
+        let lyrics = Lyrics::parse("First line\n\nSecond line\n");
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.current(ms(1_000)), None);
+        assert_eq!(lines("First line\n\nSecond line").count(), 2);
+        assert_eq!(
+            lines("[ti:Title]\n[00:01.00]First\n\n[00:02.00]Second").collect::<Vec<_>>(),
+            ["First", "Second"]
+        );
+    }
+}
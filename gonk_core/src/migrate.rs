@@ -0,0 +1,124 @@
+//! Versioned file formats.
+//!
+//! The database, settings and playlists all start with a header holding the format version
+//! and the columns used for songs: `gonk\t4\ttitle\talbum\t...`
+//!
+//! Files without a header are version 1 and version 2 headers only contain the version.
+//! Older files are upgraded on load by running every migration after their version.
+//!
+//! New song columns must be appended to `SONG_COLUMNS`, older files are read by column name.
+use crate::db::SONG_COLUMNS;
+use std::{borrow::Cow, error::Error};
+
+pub const VERSION: u16 = 10;
+const MAGIC: &str = "gonk\t";
+
+#[derive(Debug)]
+pub struct Document<'a> {
+    pub version: u16,
+    ///The song columns in the order they are stored.
+    pub columns: Vec<String>,
+    pub body: Cow<'a, str>,
+}
+
+///Upgrades a document by one version.
+pub type Migration = fn(&mut Document) -> Result<(), Box<dyn Error>>;
+
+///A migration for formats that didn't change between versions.
+pub fn unchanged(_: &mut Document) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+///The header for files written by this version.
+pub fn header() -> String {
+    format!("{MAGIC}{VERSION}\t{}\n", SONG_COLUMNS.join("\t"))
+}
+
+///Read the header and upgrade the document to the current version.
+///
+///`migrations[0]` upgrades version 1 to 2, `migrations[1]` upgrades version 2 to 3 and so on.
+pub fn read<'a>(s: &'a str, migrations: &[Migration]) -> Result<Document<'a>, Box<dyn Error>> {
+    debug_assert_eq!(migrations.len(), VERSION as usize - 1);
+
+    let mut document = match s.strip_prefix(MAGIC) {
+        Some(s) => {
+            let (header, body) = s.split_once('\n').unwrap_or((s, ""));
+            let mut parts = header.split('\t');
+            let version = parts
+                .next()
+                .ok_or("Missing version")?
+                .trim()
+                .parse::<u16>()?;
+            let columns: Vec<String> = parts.map(String::from).collect();
+
+            Document {
+                version,
+                columns: if columns.is_empty() {
+                    legacy_columns(version)
+                } else {
+                    columns
+                },
+                body: Cow::Borrowed(body),
+            }
+        }
+        None => Document {
+            version: 1,
+            columns: legacy_columns(1),
+            body: Cow::Borrowed(s),
+        },
+    };
+
+    if document.version == 0 || document.version > VERSION {
+        return Err(format!(
+            "Version {} is not supported, the latest version is {VERSION}.",
+            document.version
+        ))?;
+    }
+
+    for migration in &migrations[document.version as usize - 1..] {
+        migration(&mut document)?;
+        document.version += 1;
+    }
+
+    Ok(document)
+}
+
+///Columns used before the header listed them.
+fn legacy_columns(version: u16) -> Vec<String> {
+    let len = if version == 1 { 7 } else { 17 };
+    SONG_COLUMNS[..len].iter().map(|c| c.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_line(document: &mut Document) -> Result<(), Box<dyn Error>> {
+        document.body = Cow::Owned(format!("migrated\n{}", document.body));
+        Ok(())
+    }
+
+    ///`add_line` upgrades version 1, the rest don't change anything.
+    fn migrations() -> [Migration; VERSION as usize - 1] {
+        std::array::from_fn(|i| if i == 0 { add_line } else { unchanged })
+    }
+
+    #[test]
+    fn upgrade() {
+        let document = read("body", &migrations()).unwrap();
+        assert_eq!(document.version, VERSION);
+        assert_eq!(document.columns.len(), 7);
+        assert_eq!(document.body, "migrated\nbody");
+
+        let document = read("gonk\t2\nbody", &migrations()).unwrap();
+        assert_eq!(document.columns.len(), 17);
+        assert_eq!(document.body, "body");
+
+        let current = format!("{}body", header());
+        let document = read(&current, &migrations()).unwrap();
+        assert_eq!(document.columns, SONG_COLUMNS);
+        assert_eq!(document.body, "body");
+
+        assert!(read("gonk\t99\nbody", &migrations()).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Ogg Vorbis and Opus comment reader.
+//!
+//! Only the first two packets of the stream are read, the identification header and the comment header.
+//!
+//! https://xiph.org/ogg/doc/framing.html
+//! https://xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-610004.2
+//! https://datatracker.ietf.org/doc/html/rfc7845#section-5.2
+use crate::{
+    cover::{self, Picture},
+    db::ScanError,
+    vorbis_comment, Song,
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+pub fn read_ogg<P: AsRef<Path>>(path: P) -> Result<Song, Box<dyn Error>> {
+    let file = File::open(&path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut song = Song::default();
+    song.path = path.as_ref().to_path_buf();
+
+    let (serial, packets) = read_packets(&mut reader, 2)?;
+    let [identification, comment] = packets.as_slice() else {
+        return Err("Missing Ogg comment header.")?;
+    };
+
+    //Opus always decodes at 48kHz and the granule position includes the pre-skip.
+    let (comments, pre_skip) = if identification.starts_with(b"\x01vorbis") {
+        let header = identification.get(..28).ok_or("Invalid Vorbis header.")?;
+        song.channels = header[11];
+        song.sample_rate = u32::from_le_bytes(header[12..16].try_into()?);
+        let nominal = i32::from_le_bytes(header[20..24].try_into()?);
+        song.bitrate = nominal.max(0) as u32 / 1000;
+        (comment.strip_prefix(b"\x03vorbis"), 0)
+    } else if identification.starts_with(b"OpusHead") {
+        let header = identification.get(..19).ok_or("Invalid Opus header.")?;
+        song.channels = header[9];
+        song.sample_rate = 48000;
+        let pre_skip = u16::from_le_bytes(header[10..12].try_into()?);
+        (comment.strip_prefix(b"OpusTags"), pre_skip as u64)
+    } else {
+        return Err(ScanError::UnsupportedFormat(
+            "Unsupported Ogg stream.".to_string(),
+        ))?;
+    };
+
+    let comments = comments.ok_or("Invalid Ogg comment header.")?;
+    read_comments(comments, &mut song)?;
+
+    //Missing durations aren't fatal.
+    if let Ok(granule) = last_granule(reader.get_mut(), serial) {
+        if song.sample_rate != 0 {
+            song.duration = granule.saturating_sub(pre_skip) as f32 / song.sample_rate as f32;
+        }
+    }
+
+    Ok(song)
+}
+
+///The granule position of the stream's last page is the total number of samples.
+///
+///Pages of other multiplexed streams and pages where no packet ends (granule -1) are skipped.
+pub fn last_granule<R: Read + Seek>(file: &mut R, serial: u32) -> Result<u64, Box<dyn Error>> {
+    //The maximum size of an Ogg page.
+    const MAX_PAGE: u64 = 65307;
+
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(len.saturating_sub(MAX_PAGE)))?;
+
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let mut end = buffer.len();
+    while let Some(i) = buffer[..end]
+        .windows(4)
+        .rposition(|window| window == b"OggS")
+    {
+        end = i;
+        let Some(header) = buffer.get(i..i + 18) else {
+            continue;
+        };
+        let granule = u64::from_le_bytes(header[6..14].try_into()?);
+        let page_serial = u32::from_le_bytes(header[14..18].try_into()?);
+        if page_serial == serial && granule != u64::MAX {
+            return Ok(granule);
+        }
+    }
+
+    Err("Could not find the last Ogg page.")?
+}
+
+///The serial number of a logical stream and its packets.
+type Stream = (u32, Vec<Vec<u8>>);
+
+///Read the first `count` packets of the first logical stream and its serial number.
+///
+///Packets can span several pages, so the segment table is used to join them.
+pub fn read_packets<R: Read>(reader: &mut R, count: usize) -> Result<Stream, Box<dyn Error>> {
+    let mut packets = Vec::with_capacity(count);
+    let mut packet = Vec::new();
+    let mut serial = None;
+
+    while packets.len() < count {
+        let mut header = [0; 27];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != b"OggS" {
+            return Err(ScanError::UnsupportedFormat("File is not Ogg.".to_string()))?;
+        }
+
+        let page_serial = u32::from_le_bytes(header[14..18].try_into()?);
+        let mut segments = vec![0; header[26] as usize];
+        reader.read_exact(&mut segments)?;
+
+        let mut body = vec![0; segments.iter().map(|s| *s as usize).sum()];
+        reader.read_exact(&mut body)?;
+
+        //Skip pages from other multiplexed streams.
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut i = 0;
+        for segment in segments {
+            let segment = segment as usize;
+            packet.extend_from_slice(&body[i..i + segment]);
+            i += segment;
+
+            //A lacing value less than 255 ends the packet.
+            if segment < 255 {
+                packets.push(std::mem::take(&mut packet));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok((serial.unwrap_or_default(), packets))
+}
+
+///https://www.xiph.org/vorbis/doc/v-comment.html
+pub fn read_comments(bytes: &[u8], song: &mut Song) -> Result<(), Box<dyn Error>> {
+    comments(bytes, |k, v| vorbis_comment(song, k, v))
+}
+
+///Call `f` with the key and value of each comment.
+pub fn comments(bytes: &[u8], mut f: impl FnMut(&str, &str)) -> Result<(), Box<dyn Error>> {
+    let mut i = 0;
+
+    let vendor_length = u32_le(bytes, &mut i)?;
+    i += vendor_length;
+
+    let comment_list_length = u32_le(bytes, &mut i)?;
+    for _ in 0..comment_list_length {
+        let length = u32_le(bytes, &mut i)?;
+        let buffer = bytes
+            .get(i..i + length)
+            .ok_or("Truncated comment header.")?;
+        i += length;
+
+        let tag = String::from_utf8_lossy(buffer);
+        let (k, v) = match tag.split_once('=') {
+            Some((left, right)) => (left, right),
+            None => (tag.as_ref(), ""),
+        };
+
+        f(k, v);
+    }
+
+    Ok(())
+}
+
+///Pictures are stored as base64 encoded FLAC picture blocks in METADATA_BLOCK_PICTURE comments.
+pub fn read_ogg_pictures<P: AsRef<Path>>(path: P) -> Result<Vec<Picture>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (_, packets) = read_packets(&mut reader, 2)?;
+    let comment = packets.get(1).ok_or("Missing Ogg comment header.")?;
+    let header = comment
+        .strip_prefix(b"\x03vorbis")
+        .or_else(|| comment.strip_prefix(b"OpusTags"))
+        .ok_or("Invalid Ogg comment header.")?;
+
+    let mut pictures = Vec::new();
+    comments(header, |k, v| {
+        if k.eq_ignore_ascii_case("metadata_block_picture") {
+            if let Some(picture) = cover::base64(v).and_then(|block| Picture::flac(&block).ok()) {
+                pictures.push(picture);
+            }
+        }
+    })?;
+    Ok(pictures)
+}
+
+fn u32_le(bytes: &[u8], i: &mut usize) -> Result<usize, Box<dyn Error>> {
+    let slice = bytes.get(*i..*i + 4).ok_or("Truncated comment header.")?;
+    *i += 4;
+    Ok(u32::from_le_bytes(slice.try_into()?) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn page(serial: u32, body: &[u8], segments: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend([0; 10]);
+        page.extend(serial.to_le_bytes());
+        page.extend([0; 8]);
+        page.push(segments.len() as u8);
+        page.extend(segments);
+        page.extend(body);
+        page
+    }
+
+    #[test]
+    fn packets() {
+        let id = b"\x01vorbis".to_vec();
+        let comment = vec![7; 300];
+
+        let mut ogg = page(1, &id, &[id.len() as u8]);
+        //Another logical stream that should be ignored.
+        ogg.extend(page(2, &[0; 4], &[4]));
+        //The comment packet continues on the next page.
+        ogg.extend(page(1, &comment[..255], &[255]));
+        ogg.extend(page(1, &comment[255..], &[45]));
+
+        let packets = read_packets(&mut Cursor::new(ogg), 2).unwrap();
+        assert_eq!(packets, (1, vec![id, comment]));
+    }
+
+    #[test]
+    fn granule() {
+        let page = |serial, granule: u64| {
+            let mut page = page(serial, &[0; 4], &[4]);
+            page[6..14].copy_from_slice(&granule.to_le_bytes());
+            page
+        };
+        let mut ogg = page(1, 1000);
+        ogg.extend(page(2, 5000));
+        //No packet ends on this page.
+        ogg.extend(page(1, u64::MAX));
+
+        assert_eq!(last_granule(&mut Cursor::new(&ogg), 1).unwrap(), 1000);
+        assert_eq!(last_granule(&mut Cursor::new(&ogg), 2).unwrap(), 5000);
+        assert!(last_granule(&mut Cursor::new(&ogg), 3).is_err());
+    }
+
+    #[test]
+    fn comments() {
+        let mut bytes = Vec::new();
+        bytes.extend(4u32.to_le_bytes());
+        bytes.extend(b"gonk");
+        bytes.extend(2u32.to_le_bytes());
+        for comment in ["TITLE=Title", "ARTIST=Artist"] {
+            bytes.extend((comment.len() as u32).to_le_bytes());
+            bytes.extend(comment.as_bytes());
+        }
+
+        let mut song = Song::default();
+        read_comments(&bytes, &mut song).unwrap();
+        assert_eq!(song.title, "Title");
+        assert_eq!(song.artist, "Artist");
+    }
+}
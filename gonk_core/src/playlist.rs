@@ -2,7 +2,10 @@
 //!
 //! Each playlist has it's own file.
 //!
-use crate::{escape, gonk_path, Deserialize, Index, Serialize, Song};
+use crate::{
+    db::{self, parse_songs},
+    escape, gonk_path, log, migrate, walk, Deserialize, Index, Serialize, Song,
+};
 use std::{
     fs::{self},
     path::PathBuf,
@@ -39,10 +42,10 @@ impl Playlist {
 
 impl Serialize for Playlist {
     fn serialize(&self) -> String {
-        let mut buffer = String::new();
+        let mut buffer = migrate::header();
         buffer.push_str(&self.name);
         buffer.push('\t');
-        buffer.push_str(self.path.to_str().unwrap());
+        buffer.push_str(&db::encode_path(&self.path));
         buffer.push('\n');
         buffer.push_str(&self.songs.serialize());
         buffer
@@ -53,29 +56,75 @@ impl Deserialize for Playlist {
     type Error = Box<dyn std::error::Error>;
 
     fn deserialize(s: &str) -> Result<Self, Self::Error> {
-        let (start, end) = s.split_once('\n').ok_or("Invalid playlist")?;
+        let document = migrate::read(s, &MIGRATIONS)?;
+        let (start, end) = document.body.split_once('\n').ok_or("Invalid playlist")?;
         let (name, path) = start.split_once('\t').ok_or("Invalid playlsit")?;
 
+        let (songs, errors) = parse_songs(end, &document.columns);
+        if !errors.is_empty() {
+            log!("{} songs in {name} could not be read.", errors.len());
+        }
+
         Ok(Self {
             name: name.to_string(),
-            path: PathBuf::from(path),
-            songs: Index::from(Vec::<Song>::deserialize(end)?),
+            path: db::decode_path(path)?,
+            songs: Index::from(songs),
         })
     }
 }
 
+const MIGRATIONS: [migrate::Migration; migrate::VERSION as usize - 1] = [
+    migrate::unchanged,
+    migrate::unchanged,
+    escape_paths,
+    migrate::unchanged,
+    migrate::unchanged,
+    migrate::unchanged,
+    escape_playlist_path,
+    migrate::unchanged,
+    migrate::unchanged,
+];
+
+fn escape_paths(document: &mut migrate::Document) -> Result<(), Box<dyn std::error::Error>> {
+    db::escape_paths(document, 1)
+}
+
+///Version 8 encodes the path of the playlist too, so `%` in older paths needs to be escaped.
+fn escape_playlist_path(
+    document: &mut migrate::Document,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some((start, end)) = document.body.split_once('\n') else {
+        return Ok(());
+    };
+    let Some((name, path)) = start.split_once('\t') else {
+        return Ok(());
+    };
+    if path.contains('%') {
+        let body = format!("{name}\t{}\n{end}", path.replace('%', "%25"));
+        document.body = std::borrow::Cow::Owned(body);
+    }
+    Ok(())
+}
+
 pub fn playlists() -> Vec<Playlist> {
-    winwalk::walkdir(gonk_path().to_str().unwrap(), 0)
+    walk::walk(gonk_path(), &["playlist"])
         .into_iter()
-        .flatten()
-        .filter(|entry| match entry.extension() {
-            Some(ex) => {
-                matches!(ex.to_str(), Some("playlist"))
+        .flat_map(|path| {
+            let string = fs::read_to_string(&path).ok()?;
+            match Playlist::deserialize(&string) {
+                Ok(playlist) => {
+                    //Upgrade playlists so they only have to be migrated once.
+                    if !string.starts_with(&migrate::header()) {
+                        let _ = playlist.save();
+                    }
+                    Some(playlist)
+                }
+                Err(err) => {
+                    log!("Could not read playlist {}: {err}", path.display());
+                    None
+                }
             }
-            None => false,
         })
-        .flat_map(|entry| fs::read_to_string(entry.path))
-        .map(|string| Playlist::deserialize(&string).unwrap())
         .collect()
 }
 
@@ -85,10 +134,33 @@ mod tests {
 
     #[test]
     fn playlist() {
-        let playlist = Playlist::new("name", vec![Song::example(), Song::example()]);
+        //Lyrics and comments are looked up in the database instead of stored.
+        let song = Song {
+            lyrics: String::new(),
+            comment: String::new(),
+            ..Song::example()
+        };
+        let playlist = Playlist::new("name", vec![song.clone(), song]);
         let string = playlist.serialize();
         let p = Playlist::deserialize(&string).unwrap();
         assert_eq!(playlist, p);
+
+        //Version 7 didn't encode the path of the playlist.
+        let v7 = format!(
+            "gonk\t7\t{}\nname\t100% name.playlist\n",
+            db::SONG_COLUMNS.join("\t")
+        );
+        let p = Playlist::deserialize(&v7).unwrap();
+        assert_eq!(p.path, PathBuf::from("100% name.playlist"));
+
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            let mut playlist = Playlist::new("name", Vec::new());
+            playlist.path = PathBuf::from(OsStr::from_bytes(b"invalid \xff.playlist"));
+            let p = Playlist::deserialize(&playlist.serialize()).unwrap();
+            assert_eq!(playlist, p);
+        }
     }
 
     #[test]
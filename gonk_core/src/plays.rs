@@ -0,0 +1,123 @@
+//! Play counts
+//!
+//! Stored in `plays.db` with one song per line: `id\tcount\tlast played`.
+//! Songs are referenced by `SongId` so counts survive moving files and editing tags.
+//! The last played time is in seconds since the unix epoch.
+use crate::{db::SongId, gonk_path, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub fn path() -> PathBuf {
+    gonk_path().join("plays.db")
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Plays {
+    pub count: u32,
+    pub last_played: u64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PlayCounts {
+    plays: HashMap<SongId, Plays>,
+}
+
+impl PlayCounts {
+    ///Read the play counts, lines that can't be read are skipped.
+    pub fn load() -> Self {
+        match fs::read_to_string(path()) {
+            Ok(string) => Self::deserialize(&string).unwrap_or_default(),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut temp = path();
+        temp.set_extension("tmp");
+        fs::write(&temp, self.serialize())?;
+        fs::rename(temp, path())
+    }
+
+    pub fn get(&self, id: SongId) -> Plays {
+        self.plays.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn count(&self, id: SongId) -> u32 {
+        self.get(id).count
+    }
+
+    ///Count a play of the song.
+    pub fn played(&mut self, id: SongId) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let plays = self.plays.entry(id).or_default();
+        plays.count += 1;
+        plays.last_played = now;
+    }
+}
+
+impl Serialize for PlayCounts {
+    fn serialize(&self) -> String {
+        let mut plays: Vec<_> = self.plays.iter().collect();
+        plays.sort_unstable_by_key(|(id, _)| **id);
+
+        let mut buffer = String::new();
+        for (id, plays) in plays {
+            buffer.push_str(&format!(
+                "{}\t{}\t{}\n",
+                id.0, plays.count, plays.last_played
+            ));
+        }
+        buffer
+    }
+}
+
+impl Deserialize for PlayCounts {
+    type Error = Box<dyn Error>;
+
+    fn deserialize(s: &str) -> Result<Self, Self::Error> {
+        let parse = |line: &str| -> Result<(SongId, Plays), Box<dyn Error>> {
+            let mut parts = line.split('\t');
+            let mut next = || parts.next().ok_or("Invalid play count");
+            let id = SongId(next()?.parse()?);
+            let count = next()?.parse()?;
+            let last_played = next()?.parse()?;
+            Ok((id, Plays { count, last_played }))
+        };
+
+        Ok(Self {
+            plays: s
+                .lines()
+                .filter(|line| !line.is_empty())
+                .flat_map(parse)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays() {
+        let (a, b) = (SongId(1), SongId(2));
+        let mut counts = PlayCounts::default();
+        counts.played(a);
+        counts.played(a);
+        counts.played(b);
+        assert_eq!(counts.count(a), 2);
+        assert_eq!(counts.count(SongId(3)), 0);
+        assert!(counts.get(b).last_played > 0);
+
+        let string = format!("{}invalid line\n", counts.serialize());
+        assert_eq!(PlayCounts::deserialize(&string).unwrap(), counts);
+    }
+}
@@ -0,0 +1,595 @@
+//! Search query language
+//!
+//! ```text
+//! artist:radiohead album:"ok computer" year:>1995 genre:jazz -live
+//! ```
+//!
+//! - Terms are separated by spaces and every term has to match.
+//! - `OR` or `|` between terms matches either side, terms next to each other bind tighter.
+//! - `field:value` matches part of a field, `field:"quoted value"` can contain spaces.
+//! - `year`, `track` and `disc` can be compared with `<`, `<=`, `=`, `>=` and `>`.
+//! - `-` before a term excludes songs that match it.
+//! - Terms without a field are fuzzy matched against the title, album and artist.
+//! - `lyrics` and `comment` match a single line, which is returned as a snippet.
+//!
+//! Queries that only have plain terms are searched with the `SearchIndex`.
+use crate::{
+    db::Song,
+    lyrics,
+    search::{self, SearchIndex, Snippet},
+};
+use std::{borrow::Cow, error::Error, fmt, ops::Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Artist,
+    Album,
+    Title,
+    Genre,
+    Composer,
+    Year,
+    Track,
+    Disc,
+    Lyrics,
+    Comment,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "title" => Field::Title,
+            "genre" => Field::Genre,
+            "composer" => Field::Composer,
+            "year" => Field::Year,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "lyrics" => Field::Lyrics,
+            "comment" => Field::Comment,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Year | Field::Track | Field::Disc)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn compare(self, a: u16, b: u16) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    ///Fuzzy matched against the title, album and artist.
+    Text(String),
+    Field(Field, String),
+    Number(Field, Comparison, u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+///Groups of terms separated by `OR`, every term in a group has to match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>,
+    ///Whether values were normalized with transliteration, names are normalized the same way.
+    pub transliterate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    ///Byte range of the query that caused the error.
+    pub range: Range<usize>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+fn error<T>(message: impl Into<String>, range: Range<usize>) -> Result<T, ParseError> {
+    Err(ParseError {
+        message: message.into(),
+        range,
+    })
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    i: usize,
+    transliterate: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.i..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.i += rest.len() - rest.trim_start().len();
+    }
+
+    fn is_or(&self) -> bool {
+        let rest = self.rest();
+        rest.starts_with('|')
+            || (rest.starts_with("OR") && rest[2..].chars().next().is_none_or(char::is_whitespace))
+    }
+
+    ///A quoted phrase or everything up to the next space.
+    fn value(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.i;
+        if let Some(quoted) = self.rest().strip_prefix('"') {
+            return match quoted.find('"') {
+                Some(end) => {
+                    self.i += end + 2;
+                    Ok(&quoted[..end])
+                }
+                None => error("Missing closing quote", start..self.s.len()),
+            };
+        }
+
+        let len = self
+            .rest()
+            .find(char::is_whitespace)
+            .unwrap_or(self.rest().len());
+        self.i += len;
+        Ok(&self.s[start..self.i])
+    }
+
+    ///`None` for values without anything to search for, like `artist:"!!"`, which would match every song.
+    fn term(&mut self) -> Result<Option<Term>, ParseError> {
+        let start = self.i;
+        let negated = self.rest().starts_with('-');
+        if negated {
+            self.i += 1;
+            if self.rest().is_empty() || self.rest().starts_with(char::is_whitespace) {
+                return error("Nothing to exclude after '-'", start..self.i);
+            }
+        }
+
+        //Field names are letters followed by a colon.
+        let rest = self.rest();
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        if name_len > 0 && rest[name_len..].starts_with(':') {
+            let name = &rest[..name_len];
+            let Some(field) = Field::parse(name) else {
+                return error(format!("Unknown field '{name}'"), self.i..self.i + name_len);
+            };
+            self.i += name_len + 1;
+            let value_start = self.i;
+            let value = self.value()?;
+            let range = value_start..self.i;
+
+            if value.is_empty() {
+                return error(format!("Missing value for '{name}'"), start..self.i);
+            }
+
+            let filter = if field.is_numeric() {
+                let (comparison, number) = match value.as_bytes() {
+                    [b'<', b'=', ..] => (Comparison::LessEqual, &value[2..]),
+                    [b'>', b'=', ..] => (Comparison::GreaterEqual, &value[2..]),
+                    [b'<', ..] => (Comparison::Less, &value[1..]),
+                    [b'>', ..] => (Comparison::Greater, &value[1..]),
+                    [b'=', ..] => (Comparison::Equal, &value[1..]),
+                    _ => (Comparison::Equal, value),
+                };
+                match number.trim().parse() {
+                    Ok(number) => Filter::Number(field, comparison, number),
+                    Err(_) => return error(format!("'{number}' is not a number"), range),
+                }
+            } else {
+                let value = search::normalize(value, self.transliterate);
+                if value.is_empty() {
+                    return Ok(None);
+                }
+                Filter::Field(field, value)
+            };
+
+            return Ok(Some(Term { negated, filter }));
+        }
+
+        let value = self.value()?;
+        if value.trim().is_empty() {
+            return error("Empty phrase", start..self.i);
+        }
+        let value = search::normalize(value, self.transliterate);
+        if value.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Term {
+            negated,
+            filter: Filter::Text(value),
+        }))
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str, transliterate: bool) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            s,
+            i: 0,
+            transliterate,
+        };
+        let mut groups = vec![Vec::new()];
+        //Whether the current group has any terms, including ones without anything to search for.
+        let mut empty = true;
+        //Where the last OR is in the query.
+        let mut or = 0..0;
+
+        loop {
+            parser.skip_whitespace();
+            if parser.rest().is_empty() {
+                break;
+            }
+
+            let start = parser.i;
+            if parser.is_or() {
+                parser.i += if parser.rest().starts_with('|') { 1 } else { 2 };
+                or = start..parser.i;
+                if empty {
+                    return error("Nothing before OR", or);
+                }
+                groups.push(Vec::new());
+                empty = true;
+                continue;
+            }
+
+            if let Some(term) = parser.term()? {
+                groups.last_mut().unwrap().push(term);
+            }
+            empty = false;
+        }
+
+        if groups.len() > 1 && empty {
+            return error("Nothing after OR", or);
+        }
+        //Groups whose terms were all ignored still match everything.
+        if empty {
+            groups.clear();
+        }
+
+        //Lyrics are the slowest to check, so songs are ruled out by everything else first.
+        for group in &mut groups {
+            group.sort_by_key(|term| {
+                matches!(
+                    term.filter,
+                    Filter::Field(Field::Lyrics | Field::Comment, _)
+                )
+            });
+        }
+
+        Ok(Self {
+            groups,
+            transliterate,
+        })
+    }
+
+    ///Queries with only plain terms can use the search index.
+    pub fn is_plain(&self) -> bool {
+        self.groups.len() <= 1
+            && self
+                .groups
+                .iter()
+                .flatten()
+                .all(|term| !term.negated && matches!(term.filter, Filter::Text(_)))
+    }
+
+    ///The plain terms, used to fuzzy match and highlight names.
+    pub fn text(&self) -> String {
+        let mut words: Vec<&str> = Vec::new();
+        for term in self.groups.iter().flatten() {
+            if let (false, Filter::Text(text)) = (term.negated, &term.filter) {
+                if !words.contains(&text.as_str()) {
+                    words.push(text);
+                }
+            }
+        }
+        words.join(" ")
+    }
+
+    ///How well a song listed under `artist` matches, `None` if it doesn't.
+    ///
+    ///Names already in `index` aren't normalized again.
+    pub fn score(
+        &self,
+        song: &Song,
+        artist: &str,
+        text: &Text,
+        index: &SearchIndex,
+    ) -> Option<f64> {
+        self.groups
+            .iter()
+            .filter_map(|group| group_score(group, song, artist, text, index, self.transliterate))
+            .max_by(f64::total_cmp)
+    }
+
+    ///The first line of lyrics or comment that a `lyrics:` or `comment:` term matched.
+    pub fn snippet(&self, text: &Text) -> Option<Snippet> {
+        self.groups
+            .iter()
+            .flatten()
+            .find_map(|term| match &term.filter {
+                Filter::Field(field @ (Field::Lyrics | Field::Comment), value) if !term.negated => {
+                    let text = text.matching_line(*field, value)?.to_string();
+                    let highlights = search::highlights(&text, value, self.transliterate);
+                    Some(Snippet { text, highlights })
+                }
+                _ => None,
+            })
+    }
+}
+
+///The lines of a song's lyrics and comment with their normalized form.
+///
+///Lyrics are slow to parse, so this is done once when the database is loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Text {
+    ///Each line as `(normalized, line)`.
+    lyrics: Vec<(String, String)>,
+    comment: Vec<(String, String)>,
+}
+
+impl Text {
+    pub fn new(song: &Song, transliterate: bool) -> Self {
+        let line = |line: String| (search::normalize(&line, transliterate), line);
+        Self {
+            lyrics: lyrics::lines(&song.lyrics).map(line).collect(),
+            comment: song
+                .comment
+                .lines()
+                .map(|l| line(l.trim().to_string()))
+                .collect(),
+        }
+    }
+
+    ///The first line containing a normalized value.
+    fn matching_line(&self, field: Field, value: &str) -> Option<&str> {
+        let lines = if field == Field::Lyrics {
+            &self.lyrics
+        } else {
+            &self.comment
+        };
+        lines
+            .iter()
+            .find(|(normalized, _)| normalized.contains(value))
+            .map(|(_, line)| line.as_str())
+    }
+}
+
+fn group_score(
+    group: &[Term],
+    song: &Song,
+    artist: &str,
+    text: &Text,
+    index: &SearchIndex,
+    transliterate: bool,
+) -> Option<f64> {
+    let normalize = |name: &str| match index.folded(name) {
+        Some(folded) => Cow::Borrowed(folded),
+        None => Cow::Owned(search::normalize(name, transliterate)),
+    };
+    let mut total = 0.0;
+    let mut fuzzy = 0;
+
+    for term in group {
+        let matched = match &term.filter {
+            //Excluding fuzzy matches would hide too much.
+            Filter::Text(text) if term.negated => names(song, artist)
+                .into_iter()
+                .any(|name| normalize(name).contains(text.as_str())),
+            Filter::Text(text) => {
+                let best = names(song, artist)
+                    .into_iter()
+                    .filter_map(|name| search::score(text, &normalize(name)))
+                    .max_by(f64::total_cmp);
+                match best {
+                    Some(score) => {
+                        total += score;
+                        fuzzy += 1;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Filter::Field(field @ (Field::Lyrics | Field::Comment), value) => {
+                text.matching_line(*field, value).is_some()
+            }
+            Filter::Field(field, value) => field_values(*field, song, artist)
+                .into_iter()
+                .any(|name| normalize(name).contains(value.as_str())),
+            Filter::Number(field, comparison, number) => {
+                let value = match field {
+                    Field::Year => song.year,
+                    Field::Track => song.track_number,
+                    _ => song.disc_number,
+                };
+                comparison.compare(value, *number)
+            }
+        };
+
+        if matched == term.negated {
+            return None;
+        }
+    }
+
+    if fuzzy == 0 {
+        Some(1.0)
+    } else {
+        Some(total / fuzzy as f64)
+    }
+}
+
+fn names<'a>(song: &'a Song, artist: &'a str) -> Vec<&'a str> {
+    let mut names = vec![song.title.as_str(), song.album.as_str()];
+    names.extend(field_values(Field::Artist, song, artist));
+    names
+}
+
+fn field_values<'a>(field: Field, song: &'a Song, artist: &'a str) -> Vec<&'a str> {
+    match field {
+        Field::Artist => {
+            let mut artists = vec![artist, song.artist.as_str()];
+            artists.extend(song.track_artists.iter().map(String::as_str));
+            artists
+        }
+        Field::Album => vec![song.album.as_str()],
+        Field::Title => vec![song.title.as_str()],
+        Field::Genre => song.genres.iter().map(String::as_str).collect(),
+        Field::Composer => song.composers.iter().map(String::as_str).collect(),
+        Field::Year | Field::Track | Field::Disc | Field::Lyrics | Field::Comment => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(negated: bool, filter: Filter) -> Term {
+        Term { negated, filter }
+    }
+
+    #[test]
+    fn parse() {
+        let query = Query::parse(
+            r#"artist:Radiohead album:"OK Computer" year:>1995 genre:jazz -live"#,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            query.groups,
+            [vec![
+                term(false, Filter::Field(Field::Artist, "radiohead".into())),
+                term(false, Filter::Field(Field::Album, "ok computer".into())),
+                term(
+                    false,
+                    Filter::Number(Field::Year, Comparison::Greater, 1995)
+                ),
+                term(false, Filter::Field(Field::Genre, "jazz".into())),
+                term(true, Filter::Text("live".into())),
+            ]]
+        );
+        assert!(!query.is_plain());
+
+        let query = Query::parse("a b OR c | year:<=2000", true).unwrap();
+        assert_eq!(query.groups.len(), 3);
+        assert_eq!(query.text(), "a b c");
+
+        let query = Query::parse(r#"  come "here comes"  "#, true).unwrap();
+        assert!(query.is_plain());
+        assert_eq!(query.text(), "come here comes");
+
+        //Colons in plain terms are fine when they aren't after a field name.
+        assert!(Query::parse("12:00", true).unwrap().is_plain());
+        assert!(Query::parse("", true).unwrap().groups.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let error = |s: &str| Query::parse(s, true).unwrap_err();
+
+        assert_eq!(error("mood:happy").range, 0..4);
+        assert_eq!(error(r#"album:"ok computer"#).range, 6..18);
+        assert_eq!(error("year:>nineties").range, 5..14);
+        assert_eq!(error("artist:").message, "Missing value for 'artist'");
+        assert_eq!(error("a - b").range, 2..3);
+        assert_eq!(error("OR a").message, "Nothing before OR");
+        assert_eq!(error("a OR").message, "Nothing after OR");
+        assert_eq!(error("a OR").range, 2..4);
+        //Ranges are in bytes, so they can slice queries with wider characters.
+        assert_eq!(error("a\u{3000}|").range, 4..5);
+        assert_eq!(error("a\u{3000}OR\u{3000}").range, 4..6);
+        assert_eq!(error("\u{3000}| a").range, 3..4);
+    }
+
+    #[test]
+    fn matches() {
+        let song = Song {
+            title: "Paranoid Android".into(),
+            album: "OK Computer".into(),
+            artist: "Radiohead".into(),
+            year: 1997,
+            genres: vec!["Alternative".into(), "Rock".into()],
+            ..Song::example()
+        };
+        let text = Text::new(&song, true);
+        let matches = |s: &str| {
+            let query = Query::parse(s, true).unwrap();
+            query
+                .score(&song, "Radiohead", &text, &SearchIndex::default())
+                .is_some()
+        };
+
+        assert!(matches(
+            r#"artist:radiohead album:"ok computer" year:>1995"#
+        ));
+        assert!(matches("genre:rock paranoid"));
+        assert!(matches("year:1990 OR year:>=1997"));
+        assert!(matches("-live"));
+        assert!(!matches("-android"));
+        assert!(!matches("year:<1997"));
+        assert!(!matches("genre:jazz"));
+        assert!(!matches("artist:radiohead zzzzzz"));
+        //Nothing to search for, so they don't filter anything.
+        assert!(matches(r#"artist:"!!""#));
+        assert!(matches("!! OR year:1990"));
+    }
+
+    #[test]
+    fn snippets() {
+        let song = Song {
+            lyrics:
+                "[ti:Airbag]\n[00:10.00]In the next world war\n[00:14.00]In a jackknifed juggernaut"
+                    .into(),
+            comment: "Recorded at St. Catherine's Court".into(),
+            ..Song::example()
+        };
+        let text = Text::new(&song, true);
+        let snippet = |s: &str| {
+            let query = Query::parse(s, true).unwrap();
+            query.score(&song, "artist", &text, &SearchIndex::default())?;
+            query.snippet(&text)
+        };
+
+        let found = snippet(r#"lyrics:"next world""#).unwrap();
+        assert_eq!(found.text, "In the next world war");
+        assert_eq!(found.highlights, [7..11, 12..17]);
+        assert_eq!(
+            snippet("comment:catherines").unwrap().text,
+            "Recorded at St. Catherine's Court"
+        );
+        //Tags aren't part of the lyrics.
+        assert!(snippet("lyrics:airbag").is_none());
+        assert!(snippet("lyrics:juggernaut -lyrics:war").is_none());
+        assert!(snippet("year:2000").is_none());
+    }
+}
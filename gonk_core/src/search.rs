@@ -0,0 +1,653 @@
+//! Search index
+//!
+//! Built once when the database is loaded. Every artist, album and song name is normalized
+//! (see `normalize`) and split into trigrams, so a query only scores the names that share trigrams with it.
+//! Queries shorter than a trigram use a sorted list of words instead.
+//!
+//! Candidates are scored in parallel by matching the query against the whole name
+//! and word by word (see `score`), then boosted by how often they've been played.
+use crate::{strsim, vdb::Item};
+use rayon::prelude::*;
+use std::{cmp::Ordering, collections::HashMap, ops::Range};
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+///Words less similar than this aren't fuzzy matches.
+const MIN_SIMILARITY: f64 = 0.80;
+const MAX_PLAY_BOOST: f64 = 0.04;
+pub const MAX_RESULTS: usize = 40;
+
+type Trigram = [char; 3];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub item: Item,
+    pub score: f64,
+    ///Byte ranges of the item's name that matched the query, sorted and not overlapping.
+    pub highlights: Vec<Range<usize>>,
+    ///The line of lyrics or comment that matched.
+    pub snippet: Option<Snippet>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+struct Entry {
+    item: Item,
+    ///The normalized name.
+    text: String,
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    entries: Vec<Entry>,
+    trigrams: HashMap<Trigram, Vec<u32>>,
+    ///Every word and the entry it's from, sorted for prefix searches.
+    words: Vec<(String, u32)>,
+    ///The first entry with each name, so names don't have to be normalized again.
+    names: HashMap<String, u32>,
+    ///Whether Cyrillic and Greek are spelled with Latin letters, see `normalize`.
+    transliterate: bool,
+}
+
+///Latin spelling of Cyrillic and Greek letters, accents are removed beforehand.
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'а' | 'α' => "a",
+        'б' | 'β' => "b",
+        'в' => "v",
+        'г' | 'ґ' | 'γ' => "g",
+        'д' | 'δ' => "d",
+        'е' | 'э' | 'ε' => "e",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' | 'ζ' => "z",
+        'и' | 'й' | 'і' | 'η' | 'ι' => "i",
+        'к' | 'κ' => "k",
+        'л' | 'λ' => "l",
+        'м' | 'μ' => "m",
+        'н' | 'ν' => "n",
+        'о' | 'ο' | 'ω' => "o",
+        'п' | 'π' => "p",
+        'р' | 'ρ' => "r",
+        'с' | 'σ' | 'ς' => "s",
+        'т' | 'τ' => "t",
+        'у' => "u",
+        'ф' | 'φ' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ы' | 'υ' => "y",
+        'ю' => "yu",
+        'я' => "ya",
+        'θ' => "th",
+        'ξ' => "x",
+        'χ' => "ch",
+        'ψ' => "ps",
+        _ => return None,
+    })
+}
+
+///Latin spelling of letters that don't decompose into a letter and an accent.
+fn latin(c: char) -> Option<&'static str> {
+    Some(match c {
+        'æ' => "ae",
+        'đ' | 'ð' => "d",
+        'ħ' => "h",
+        'ı' => "i",
+        'ł' => "l",
+        'ø' => "o",
+        'œ' => "oe",
+        'ß' => "ss",
+        'ŧ' => "t",
+        'þ' => "th",
+        _ => return None,
+    })
+}
+
+///Accents used by Latin, Greek and Cyrillic.
+///
+///Other scripts have their own marks, like the vowel signs in Devanagari, which are kept.
+fn is_accent(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+///Decompose a character (NFKD), remove its accents and lowercase it.
+///
+///Shared by searching and sorting (see `collate`) so both treat names the same way.
+pub fn fold(c: char, mut f: impl FnMut(char)) {
+    decompose_compatible(c, |c| {
+        if is_accent(c) {
+            return;
+        }
+        for c in c.to_lowercase() {
+            match latin(c) {
+                Some(latin) => latin.chars().for_each(&mut f),
+                None => f(c),
+            }
+        }
+    });
+}
+
+///Append the searchable form of a character.
+///
+///Characters are folded (see `fold`), punctuation is removed, Cyrillic and Greek are optionally
+///transliterated and whitespace is collapsed to single spaces.
+///"AC/DC" becomes "acdc" and "Beyoncé" becomes "beyonce".
+fn normalize_char(c: char, transliterate: bool, out: &mut String) {
+    fold(c, |c| {
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with(' ') {
+                out.push(' ');
+            }
+            return;
+        }
+        match self::transliterate(c).filter(|_| transliterate) {
+            Some(latin) => out.push_str(latin),
+            //Marks left after folding are part of the letter.
+            None if c.is_alphanumeric() || is_combining_mark(c) => out.push(c),
+            None => {}
+        }
+    });
+}
+
+pub fn normalize(s: &str, transliterate: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        normalize_char(c, transliterate, &mut out);
+    }
+    if out.ends_with(' ') {
+        out.pop();
+    }
+    out
+}
+
+///The unique trigrams in some text, padded with spaces so short words still have some.
+fn trigrams(text: &str) -> Vec<Trigram> {
+    let chars: Vec<char> = std::iter::once(' ')
+        .chain(text.chars())
+        .chain(std::iter::once(' '))
+        .collect();
+    let mut trigrams: Vec<Trigram> = chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+///Artists first, then albums, then songs in album order, used to break ties.
+fn compare(a: &Item, b: &Item) -> Ordering {
+    fn rank(item: &Item) -> (u8, u16, u16) {
+        match item {
+            Item::Artist(_) => (0, 0, 0),
+            Item::Album(_) => (1, 0, 0),
+            Item::Song((_, _, _, disc, track, _)) => (2, *disc, *track),
+        }
+    }
+    rank(a).cmp(&rank(b))
+}
+
+///How well one word of a query matches one word of a name.
+fn word_score(query: &str, word: &str) -> f64 {
+    if word == query {
+        1.0
+    } else if word.starts_with(query) {
+        0.9
+    } else if word.contains(query) {
+        0.7
+    } else {
+        let similarity = strsim::jaro_winkler(query, word);
+        if similarity >= MIN_SIMILARITY {
+            similarity * 0.8
+        } else {
+            0.0
+        }
+    }
+}
+
+///How well a normalized name matches a normalized query, `None` if it doesn't.
+///
+///Exact matches score 1.0 and names starting with the query 0.95, or 0.9 if it ends mid-word.
+///Otherwise every word of the query is matched against the best word of the name,
+///with names containing the whole query scoring at least 0.75.
+///Words of the name the query didn't match cost up to 0.05, so shorter names rank higher.
+pub(crate) fn score(query: &str, text: &str) -> Option<f64> {
+    if text == query {
+        return Some(1.0);
+    }
+
+    let words: Vec<&str> = text.split(' ').collect();
+    let mut matched = vec![false; words.len()];
+    let mut total = 0.0;
+    let mut all = true;
+    for part in query.split(' ') {
+        let (i, best) = words
+            .iter()
+            .map(|word| word_score(part, word))
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        if best == 0.0 {
+            all = false;
+            break;
+        }
+        matched[i] = true;
+        total += best;
+    }
+    let words_score = if all {
+        0.9 * total / query.split(' ').count() as f64
+    } else {
+        0.0
+    };
+
+    let score = if let Some(rest) = text.strip_prefix(query) {
+        if rest.starts_with(' ') {
+            0.95
+        } else {
+            0.9
+        }
+    } else if text.contains(query) {
+        words_score.max(0.75)
+    } else {
+        words_score
+    };
+
+    if score == 0.0 {
+        return None;
+    }
+
+    //A prefix covers the words it spans.
+    let unmatched = if text.starts_with(query) {
+        words.len() - query.split(' ').count()
+    } else {
+        matched.iter().filter(|matched| !**matched).count()
+    };
+    Some(score - 0.05 * unmatched as f64 / words.len() as f64)
+}
+
+///A small bonus for often played items, never enough to pass a better match tier.
+pub fn play_boost(count: u32) -> f64 {
+    MAX_PLAY_BOOST * (1.0 - 1.0 / (1.0 + count as f64 / 10.0))
+}
+
+///Find each word of a normalized query in a name.
+pub fn highlights(name: &str, query: &str, transliterate: bool) -> Vec<Range<usize>> {
+    //Normalize one character at a time so matches can be mapped back to the name.
+    let mut text = String::with_capacity(name.len());
+    let mut original = Vec::with_capacity(name.len());
+    for (i, c) in name.char_indices() {
+        let len = text.len();
+        normalize_char(c, transliterate, &mut text);
+        original.extend(std::iter::repeat_n(i..i + c.len_utf8(), text.len() - len));
+    }
+
+    let mut ranges: Vec<Range<usize>> = query
+        .split_whitespace()
+        .flat_map(|word| text.match_indices(word))
+        .map(|(start, word)| original[start].start..original[start + word.len() - 1].end)
+        .collect();
+    ranges.sort_unstable_by_key(|range| (range.start, range.end));
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+impl SearchIndex {
+    pub fn new(items: Vec<Item>, transliterate: bool) -> Self {
+        let entries: Vec<(Entry, Vec<Trigram>)> = items
+            .into_par_iter()
+            .map(|item| {
+                let text = normalize(item.name(), transliterate);
+                let trigrams = trigrams(&text);
+                (Entry { item, text }, trigrams)
+            })
+            .collect();
+
+        let mut index = SearchIndex {
+            transliterate,
+            ..Default::default()
+        };
+        for (i, (entry, trigrams)) in entries.into_iter().enumerate() {
+            let i = i as u32;
+            for trigram in trigrams {
+                index.trigrams.entry(trigram).or_default().push(i);
+            }
+            for word in entry.text.split_whitespace() {
+                index.words.push((word.to_string(), i));
+            }
+            index
+                .names
+                .entry(entry.item.name().to_string())
+                .or_insert(i);
+            index.entries.push(entry);
+        }
+        index.words.sort_unstable();
+        index.words.dedup();
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///The normalized form of an artist, album or song name, if it's in the index.
+    pub fn folded(&self, name: &str) -> Option<&str> {
+        let i = *self.names.get(name)?;
+        Some(&self.entries[i as usize].text)
+    }
+
+    ///Entries that could match a normalized query.
+    fn candidates(&self, query: &str) -> Vec<u32> {
+        if query.chars().count() < 3 {
+            let start = self
+                .words
+                .partition_point(|(word, _)| word.as_str() < query);
+            let mut candidates: Vec<u32> = self.words[start..]
+                .iter()
+                .take_while(|(word, _)| word.starts_with(query))
+                .map(|(_, i)| *i)
+                .collect();
+            candidates.sort_unstable();
+            candidates.dedup();
+            return candidates;
+        }
+
+        let trigrams = trigrams(query);
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for trigram in &trigrams {
+            for &i in self.trigrams.get(trigram).into_iter().flatten() {
+                *counts.entry(i).or_default() += 1;
+            }
+        }
+
+        //Each typo changes up to three trigrams.
+        let min = (trigrams.len() / 3).max(1);
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count >= min)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    ///The best matches for a query, most accurate first.
+    ///
+    ///`plays` is how often an item has been played.
+    pub fn search(&self, query: &str, plays: impl Fn(&Item) -> u32 + Sync) -> Vec<SearchResult> {
+        let query = normalize(query.trim(), self.transliterate);
+
+        if query.is_empty() {
+            return self
+                .entries
+                .iter()
+                .take(MAX_RESULTS)
+                .map(|entry| SearchResult {
+                    item: entry.item.clone(),
+                    score: 0.0,
+                    highlights: Vec::new(),
+                    snippet: None,
+                })
+                .collect();
+        }
+
+        let mut results: Vec<(u32, f64)> = self
+            .candidates(&query)
+            .into_par_iter()
+            .filter_map(|i| {
+                let entry = &self.entries[i as usize];
+                let score = score(&query, &entry.text)? + play_boost(plays(&entry.item));
+                Some((i, score))
+            })
+            .collect();
+
+        //Entries are in database order so equal matches always sort the same way.
+        let order = |(a, score_a): &(u32, f64), (b, score_b): &(u32, f64)| {
+            score_b
+                .total_cmp(score_a)
+                .then_with(|| {
+                    compare(
+                        &self.entries[*a as usize].item,
+                        &self.entries[*b as usize].item,
+                    )
+                })
+                .then_with(|| a.cmp(b))
+        };
+        if results.len() > MAX_RESULTS {
+            results.select_nth_unstable_by(MAX_RESULTS, order);
+            results.truncate(MAX_RESULTS);
+        }
+        results.sort_unstable_by(order);
+
+        results
+            .into_iter()
+            .map(|(i, score)| {
+                let item = self.entries[i as usize].item.clone();
+                let highlights = highlights(item.name(), &query, self.transliterate);
+                SearchResult {
+                    item,
+                    score,
+                    highlights,
+                    snippet: None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SongId;
+
+    fn song(title: &str, track: u16) -> Item {
+        Item::Song((
+            "The Beatles".to_string(),
+            "Abbey Road".to_string(),
+            title.to_string(),
+            1,
+            track,
+            SongId(track as u64),
+        ))
+    }
+
+    fn index() -> SearchIndex {
+        SearchIndex::new(
+            vec![
+                Item::Artist("The Beatles".to_string()),
+                Item::Album(("The Beatles".to_string(), "Abbey Road".to_string(), None)),
+                song("Come Together", 1),
+                song("Something", 2),
+                song("Here Comes the Sun", 7),
+                Item::Artist("Björk".to_string()),
+            ],
+            true,
+        )
+    }
+
+    fn names(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.item.name()).collect()
+    }
+
+    #[test]
+    fn search() {
+        let index = index();
+        assert_eq!(index.len(), 6);
+        assert_eq!(index.search("", |_| 0).len(), 6);
+
+        assert_eq!(names(&index.search("beatles", |_| 0)), ["The Beatles"]);
+        //Typos still match.
+        assert_eq!(names(&index.search("the beatels", |_| 0)), ["The Beatles"]);
+        assert_eq!(names(&index.search("BJÖRK", |_| 0)), ["Björk"]);
+        assert_eq!(names(&index.search("bjork", |_| 0)), ["Björk"]);
+        assert!(names(&index.search("sun", |_| 0)).contains(&"Here Comes the Sun"));
+        assert!(index.search("zzzz", |_| 0).is_empty());
+
+        //Short queries match the start of words.
+        let results = index.search("co", |_| 0);
+        assert_eq!(names(&results), ["Come Together", "Here Comes the Sun"]);
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(normalize("Beyoncé", true), "beyonce");
+        assert_eq!(normalize("AC/DC", true), "acdc");
+        assert_eq!(
+            normalize("  Guns N' Roses -  Live ", true),
+            "guns n roses live"
+        );
+        //Compatibility forms like ligatures and full width letters.
+        assert_eq!(normalize("ﬁnal ＡＢＣ", true), "final abc");
+        assert_eq!(normalize("Кино", true), "kino");
+        assert_eq!(
+            normalize("Sigur Rós Ærøskøbing", true),
+            "sigur ros aeroskobing"
+        );
+        assert_eq!(normalize("Ελευθερία", true), "eleytheria");
+        assert_eq!(normalize("坂本龍一", true), "坂本龍一");
+        //Decomposed accents are removed too, vowel signs in other scripts are kept.
+        assert_eq!(normalize("Beyonce\u{301}", true), "beyonce");
+        assert_eq!(normalize("हिन्दी", true), "हिन्दी");
+        //Transliteration can be turned off.
+        assert_eq!(normalize("Кино", false), "кино");
+        assert_eq!(normalize("Ελευθερία", false), "ελευθερια");
+    }
+
+    ///A small library and the results expected at the top for each query.
+    fn corpus() -> SearchIndex {
+        let album = |artist: &str, album: &str, songs: &[(&str, u16)]| {
+            let mut items = vec![Item::Album((artist.to_string(), album.to_string(), None))];
+            for (title, track) in songs {
+                items.push(Item::Song((
+                    artist.to_string(),
+                    album.to_string(),
+                    title.to_string(),
+                    1,
+                    *track,
+                    SongId::new(std::path::Path::new(title)),
+                )));
+            }
+            items
+        };
+
+        let mut items: Vec<Item> = [
+            "AC/DC",
+            "Beyoncé",
+            "Björk",
+            "Radiohead",
+            "Sun Kil Moon",
+            "The Beatles",
+            "The Sundays",
+        ]
+        .iter()
+        .map(|artist| Item::Artist(artist.to_string()))
+        .collect();
+        items.extend(album(
+            "AC/DC",
+            "Back in Black",
+            &[("Hells Bells", 1), ("Back in Black", 6)],
+        ));
+        items.extend(album(
+            "Beyoncé",
+            "Lemonade",
+            &[("Hold Up", 2), ("Sorry", 5)],
+        ));
+        items.extend(album(
+            "Radiohead",
+            "OK Computer",
+            &[("Airbag", 1), ("Paranoid Android", 2), ("Karma Police", 6)],
+        ));
+        items.extend(album("Sun Kil Moon", "Benji", &[("Carissa", 1)]));
+        items.extend(album(
+            "The Beatles",
+            "Abbey Road",
+            &[
+                ("Come Together", 1),
+                ("Something", 2),
+                ("Here Comes the Sun", 7),
+            ],
+        ));
+        SearchIndex::new(items, true)
+    }
+
+    const GOLDEN: [(&str, &[&str]); 12] = [
+        ("beatles", &["The Beatles"]),
+        ("acdc", &["AC/DC"]),
+        ("beyonce", &["Beyoncé"]),
+        //Albums rank above songs with the same name.
+        ("back in black", &["Back in Black", "Back in Black"]),
+        //Names starting with the query, then whole words, then words starting with it.
+        (
+            "sun",
+            &["Sun Kil Moon", "Here Comes the Sun", "The Sundays"],
+        ),
+        ("come", &["Come Together", "Here Comes the Sun"]),
+        ("paranoid andriod", &["Paranoid Android"]),
+        ("police karma", &["Karma Police"]),
+        ("ok comp", &["OK Computer"]),
+        ("karma", &["Karma Police"]),
+        ("the", &["The Beatles", "The Sundays", "Here Comes the Sun"]),
+        //Ties are broken by track number.
+        ("so", &["Something", "Sorry"]),
+    ];
+
+    #[test]
+    fn golden() {
+        let index = corpus();
+        for (query, expected) in GOLDEN {
+            let results = index.search(query, |_| 0);
+            assert_eq!(
+                &names(&results)[..expected.len().min(results.len())],
+                expected,
+                "{query}"
+            );
+        }
+
+        //Play counts break ties between similar matches.
+        let sorry = SongId::new(std::path::Path::new("Sorry"));
+        let results = index.search("so", |item| match item {
+            Item::Song((_, _, _, _, _, id)) if *id == sorry => 20,
+            _ => 0,
+        });
+        assert_eq!(names(&results)[..2], ["Sorry", "Something"]);
+
+        //But never lift a worse match over a better one.
+        let results = index.search("sun", |item| match item {
+            Item::Artist(artist) if artist == "The Sundays" => 1000,
+            _ => 0,
+        });
+        assert_eq!(names(&results)[2], "The Sundays");
+    }
+
+    #[test]
+    fn highlight() {
+        assert_eq!(highlights("Here Comes the Sun", "sun", true), vec![15..18]);
+        assert_eq!(highlights("Come Together", "come to", true), [0..4, 5..7]);
+        assert_eq!(highlights("Björk", "jo", true), vec![1..4]);
+        //'İ' becomes two characters when lowercased.
+        assert_eq!(highlights("İstanbul", "ist", true), vec![0..4]);
+        assert_eq!(highlights("AC/DC", "acdc", true), vec![0..5]);
+        assert!(highlights("Something", "zz", true).is_empty());
+
+        let results = index().search("come", |_| 0);
+        assert_eq!(results[0].highlights, vec![0..4]);
+    }
+}
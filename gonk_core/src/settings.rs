@@ -1,6 +1,6 @@
 //! Music player settings
 //!
-//! Stores the volume, state of the queue and output device
+//! Stores the volume, state of the queue, output device, the name used for compilations, sorting, tag separators, search and music folders
 //!
 //! TODO: Rework to a modified toml format and add volume reduction and audio packet size.
 use crate::*;
@@ -15,14 +15,25 @@ pub struct Settings {
     pub index: u16,
     pub elapsed: f32,
     pub output_device: String,
-    pub music_folder: String,
+    ///Artist that compilations and albums by several artists are listed under.
+    pub various_artists: String,
+    ///Sort artists and albums without leading articles like "The".
+    pub ignore_articles: bool,
+    ///Split tags holding several artists, genres or composers, like "Artist feat. Guest".
+    ///
+    ///Stored separated by `db::VALUE_SEPARATOR`, so separators can contain spaces.
+    pub separators: Vec<String>,
+    ///Search Cyrillic and Greek names with Latin letters, so "kino" finds "Кино".
+    pub transliterate: bool,
+    ///Library roots, every folder is scanned into the same database.
+    pub music_folders: Vec<PathBuf>,
     pub queue: Vec<Song>,
     pub file: Option<File>,
 }
 
 impl Serialize for Settings {
     fn serialize(&self) -> String {
-        let mut buffer = String::new();
+        let mut buffer = migrate::header();
         buffer.push_str(&self.volume.to_string());
         buffer.push('\t');
         buffer.push_str(&self.index.to_string());
@@ -31,7 +42,17 @@ impl Serialize for Settings {
         buffer.push('\t');
         buffer.push_str(&escape(&self.output_device));
         buffer.push('\t');
-        buffer.push_str(&escape(&self.music_folder));
+        buffer.push_str(&escape(&self.various_artists));
+        buffer.push('\t');
+        buffer.push(if self.ignore_articles { '1' } else { '0' });
+        buffer.push('\t');
+        buffer.push_str(&escape(&db::join_values(&self.separators)));
+        buffer.push('\t');
+        buffer.push(if self.transliterate { '1' } else { '0' });
+        for folder in &self.music_folders {
+            buffer.push('\t');
+            buffer.push_str(&db::encode_path(folder));
+        }
         buffer.push('\n');
         buffer.push_str(&self.queue.serialize());
         buffer
@@ -42,32 +63,152 @@ impl Deserialize for Settings {
     type Error = Box<dyn Error>;
 
     fn deserialize(s: &str) -> Result<Self, Self::Error> {
-        let (start, end) = s.split_once('\n').ok_or("Invalid settings")?;
+        let document = migrate::read(s, &MIGRATIONS)?;
+        let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
         let split: Vec<&str> = start.split('\t').collect();
-        let music_folder = if split.len() == 4 {
-            String::new()
-        } else {
-            split[4].to_string()
-        };
-
-        let queue = if end.is_empty() {
-            Vec::new()
-        } else {
-            Vec::<Song>::deserialize(end)?
-        };
+
+        if split.len() < 8 {
+            return Err("Invalid settings")?;
+        }
+
+        let (queue, errors) = db::parse_songs(end, &document.columns);
+        if !errors.is_empty() {
+            log!("{} songs in the queue could not be read.", errors.len());
+        }
 
         Ok(Self {
             volume: split[0].parse::<u8>()?,
             index: split[1].parse::<u16>()?,
             elapsed: split[2].parse::<f32>()?,
             output_device: split[3].to_string(),
-            music_folder,
+            various_artists: split[4].to_string(),
+            ignore_articles: split[5] == "1",
+            separators: db::split_values(split[6]),
+            transliterate: split[7] == "1",
+            //Older versions stored a single, possibly empty, folder.
+            music_folders: split[8..]
+                .iter()
+                .filter(|folder| !folder.is_empty())
+                .map(|folder| db::decode_path(folder))
+                .collect::<Result<_, _>>()?,
             queue,
             file: None,
         })
     }
 }
 
+const MIGRATIONS: [migrate::Migration; migrate::VERSION as usize - 1] = [
+    add_music_folder,
+    migrate::unchanged,
+    escape_paths,
+    add_various_artists,
+    add_ignore_articles,
+    add_separators,
+    migrate::unchanged,
+    separate_separators,
+    add_transliterate,
+];
+
+///Very old settings files didn't store the music folder.
+fn add_music_folder(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+
+    if start.split('\t').count() == 4 {
+        document.body = format!("{start}\t\n{end}").into();
+    }
+
+    Ok(())
+}
+
+///Escape the music folder and the paths in the queue.
+fn escape_paths(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    db::escape_paths(document, 1)?;
+
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let start: Vec<String> = start
+        .split('\t')
+        .enumerate()
+        .map(|(i, part)| {
+            if i >= 4 {
+                part.replace('%', "%25")
+            } else {
+                part.to_string()
+            }
+        })
+        .collect();
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+
+    Ok(())
+}
+
+///Store the name used for compilations before the music folders.
+fn add_various_artists(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let mut start: Vec<&str> = start.split('\t').collect();
+    if start.len() < 4 {
+        return Err("Invalid settings")?;
+    }
+    start.insert(4, db::VARIOUS_ARTISTS);
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+    Ok(())
+}
+
+///Store whether articles are ignored when sorting, on by default.
+fn add_ignore_articles(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let mut start: Vec<&str> = start.split('\t').collect();
+    if start.len() < 5 {
+        return Err("Invalid settings")?;
+    }
+    start.insert(5, "1");
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+    Ok(())
+}
+
+///Store the separators used to split multi-valued tags.
+fn add_separators(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let mut start: Vec<&str> = start.split('\t').collect();
+    if start.len() < 6 {
+        return Err("Invalid settings")?;
+    }
+    let separators = db::SEPARATORS.join(" ");
+    start.insert(6, &separators);
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+    Ok(())
+}
+
+///Version 9 separates the separators with `db::VALUE_SEPARATOR` instead of spaces.
+///
+///The old defaults split names like "AC/DC" on "/", so they're replaced with the new ones.
+fn separate_separators(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let mut start: Vec<String> = start.split('\t').map(String::from).collect();
+    if start.len() < 7 {
+        return Err("Invalid settings")?;
+    }
+    let separators: Vec<String> = if start[6] == "feat. ; /" {
+        db::SEPARATORS.iter().map(|s| s.to_string()).collect()
+    } else {
+        start[6].split_whitespace().map(String::from).collect()
+    };
+    start[6] = db::join_values(&separators);
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+    Ok(())
+}
+
+///Store whether search transliterates Cyrillic and Greek, on by default.
+fn add_transliterate(document: &mut migrate::Document) -> Result<(), Box<dyn Error>> {
+    let (start, end) = document.body.split_once('\n').ok_or("Invalid settings")?;
+    let mut start: Vec<&str> = start.split('\t').collect();
+    if start.len() < 7 {
+        return Err("Invalid settings")?;
+    }
+    start.insert(7, "1");
+    document.body = format!("{}\n{end}", start.join("\t")).into();
+    Ok(())
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -75,7 +216,11 @@ impl Default for Settings {
             index: Default::default(),
             elapsed: Default::default(),
             output_device: Default::default(),
-            music_folder: Default::default(),
+            various_artists: db::VARIOUS_ARTISTS.to_string(),
+            ignore_articles: true,
+            separators: db::SEPARATORS.iter().map(|s| s.to_string()).collect(),
+            transliterate: true,
+            music_folders: Default::default(),
             queue: Default::default(),
             file: None,
         }
@@ -116,4 +261,57 @@ mod tests {
     fn settings() {
         Settings::new().unwrap();
     }
+
+    #[test]
+    fn migrate() {
+        let v1 = "15\t0\t0\tSpeakers\ntitle\talbum\tartist\t1\t1\tpath\t1\n";
+        let settings = Settings::deserialize(v1).unwrap();
+        assert_eq!(settings.output_device, "Speakers");
+        assert_eq!(settings.various_artists, db::VARIOUS_ARTISTS);
+        assert!(settings.ignore_articles);
+        assert_eq!(settings.separators, db::SEPARATORS);
+        assert!(settings.transliterate);
+        assert!(settings.music_folders.is_empty());
+        assert_eq!(settings.queue.len(), 1);
+
+        let settings = Settings::deserialize(&settings.serialize()).unwrap();
+        assert_eq!(settings.queue[0].title, "title");
+
+        let v3 = "gonk\t3\n15\t0\t0\tSpeakers\tD:\\100% Music\n";
+        let mut settings = Settings::deserialize(v3).unwrap();
+        assert_eq!(settings.music_folders, [PathBuf::from("D:\\100% Music")]);
+
+        settings.music_folders.push(PathBuf::from("E:\\Music"));
+        settings.various_artists = String::from("Compilations");
+        settings.ignore_articles = false;
+        settings.separators = Vec::new();
+        settings.transliterate = false;
+        let mut settings = Settings::deserialize(&settings.serialize()).unwrap();
+        assert_eq!(settings.various_artists, "Compilations");
+        assert!(!settings.ignore_articles);
+        assert!(settings.separators.is_empty());
+        assert!(!settings.transliterate);
+
+        settings.separators = vec![" & ".to_string(), "feat.".to_string()];
+        let settings = Settings::deserialize(&settings.serialize()).unwrap();
+        assert_eq!(settings.separators, [" & ", "feat."]);
+        assert_eq!(
+            settings.music_folders,
+            [PathBuf::from("D:\\100% Music"), PathBuf::from("E:\\Music")]
+        );
+
+        //Version 8 separated the separators with spaces.
+        let v8 = "gonk\t8\n15\t0\t0\tSpeakers\tVA\t1\tfeat. ; /\n";
+        let settings = Settings::deserialize(v8).unwrap();
+        assert_eq!(settings.separators, db::SEPARATORS);
+        let v8 = "gonk\t8\n15\t0\t0\tSpeakers\tVA\t1\tft. |\n";
+        let settings = Settings::deserialize(v8).unwrap();
+        assert_eq!(settings.separators, ["ft.", "|"]);
+
+        //Version 9 didn't store whether search transliterates.
+        let v9 = "gonk\t9\n15\t0\t0\tSpeakers\tVA\t1\tfeat.\tD:\\Music\n";
+        let settings = Settings::deserialize(v9).unwrap();
+        assert!(settings.transliterate);
+        assert_eq!(settings.music_folders, [PathBuf::from("D:\\Music")]);
+    }
 }
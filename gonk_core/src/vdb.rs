@@ -4,60 +4,409 @@
 //!
 //! Also contains code for querying artists, albums and songs.
 //!
-use crate::db::{Album, Song};
-use crate::{database_path, strsim, Deserialize};
-use std::collections::BTreeMap;
-use std::{cmp::Ordering, fs, str::from_utf8_unchecked};
+use crate::db::{self, Album, Song, SongId};
+use crate::{
+    collate, database_path, log,
+    plays::PlayCounts,
+    query::{ParseError, Query, Text},
+    search::{self, SearchIndex, SearchResult},
+    settings::Settings,
+};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::from_utf8_unchecked,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn settings(various_artists: &str, ignore_articles: bool) -> Settings {
+        Settings {
+            various_artists: various_artists.to_string(),
+            ignore_articles,
+            ..Settings::default()
+        }
+    }
+
     #[test]
     fn db() {
-        let db = Database::new();
-        dbg!(db.artists());
-        dbg!(db.search("test"));
+        let test = Song {
+            title: "test".to_string(),
+            path: PathBuf::from("test.flac"),
+            id: SongId::new(Path::new("test.flac")),
+            track_number: 2,
+            ..Song::example()
+        };
+        let songs = vec![Song::example(), test];
+        let errors = vec!["broken line".to_string()];
+        let db = Database::from_songs(songs, errors, &settings("VA", true));
+
+        //Songs are also listed under the track artist they credit.
+        assert_eq!(db.artists(), ["artist", "track artist"]);
+        assert_eq!(db.len, 2);
+        assert_eq!(db.errors, ["broken line"]);
+
+        let results = db.search("test", &PlayCounts::default()).unwrap();
+        assert_eq!(results[0].item.name(), "test");
+        //Credited songs are only found once.
+        let found = results.iter().filter(|r| matches!(r.item, Item::Song(_)));
+        assert_eq!(found.count(), 1);
+    }
+
+    #[test]
+    fn compilations() {
+        let song = |artist: &str, album: &str, path: &str| Song {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            album_artist: String::new(),
+            track_artists: vec![artist.to_string()],
+            path: PathBuf::from(path),
+            ..Song::example()
+        };
+
+        let mut flagged = song("c", "Hits", "hits/01.flac");
+        flagged.compilation = true;
+        let mut tagged = song("d", "Album", "album/01.flac");
+        tagged.album_artist = "d".to_string();
+
+        let songs = vec![
+            song("a", "Mix", "mix/01.flac"),
+            song("b", "Mix", "mix/02.flac"),
+            song("a", "Solo", "solo/01.flac"),
+            song("a", "Solo", "solo/02.flac"),
+            flagged,
+            tagged,
+        ];
+        let db = Database::from_songs(songs, Vec::new(), &settings("VA", true));
+
+        //Songs on compilations are also listed under their own artist.
+        assert_eq!(db.artists(), ["VA", "a", "b", "c", "d"]);
+        let titles: Vec<_> = db
+            .albums_by_artist("VA")
+            .unwrap()
+            .iter()
+            .map(|a| &a.title)
+            .collect();
+        assert_eq!(titles, ["Hits", "Mix"]);
+        let folder = |name| Some(Path::new(name));
+        assert_eq!(db.album("VA", "Mix", folder("mix")).unwrap().songs.len(), 2);
+        assert_eq!(
+            db.album("a", "Solo", folder("solo")).unwrap().songs.len(),
+            2
+        );
+        assert_eq!(db.album("b", "Mix", folder("mix")).unwrap().songs.len(), 1);
+    }
+
+    #[test]
+    fn album_folders() {
+        let song = |artist: &str, album: &str, path: &str| Song {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            album_artist: String::new(),
+            track_artists: vec![artist.to_string()],
+            compilation: album == "Hits",
+            path: PathBuf::from(path),
+            ..Song::example()
+        };
+        let songs = vec![
+            song("a", "Double", "double/CD1/01.flac"),
+            song("a", "Double", "double/Disc 2/01.flac"),
+            song("b", "Hits", "hits 1/01.flac"),
+            song("c", "Hits", "hits 2/01.flac"),
+        ];
+        let db = Database::from_songs(songs, Vec::new(), &settings("VA", true));
+
+        let folder = |name| Some(Path::new(name));
+        assert_eq!(db.albums_by_artist("a").unwrap().len(), 1);
+        assert_eq!(
+            db.album("a", "Double", folder("double"))
+                .unwrap()
+                .songs
+                .len(),
+            2
+        );
+        assert_eq!(db.albums_by_artist("VA").unwrap().len(), 2);
+        let hits = db.album("VA", "Hits", folder("hits 2")).unwrap();
+        assert_eq!(hits.songs[0].artist, "c");
+
+        assert!(is_disc_folder("CD1"));
+        assert!(is_disc_folder("disk_03 - Bonus"));
+        assert!(!is_disc_folder("Discovery"));
+        assert!(!is_disc_folder("CD"));
+    }
+
+    #[test]
+    fn credits() {
+        let mut song = Song {
+            artist: "A feat. B".to_string(),
+            album_artist: String::new(),
+            track_artists: vec!["A feat. B".to_string()],
+            ..Song::example()
+        };
+        let mut other = song.clone();
+        other.track_number = 2;
+        other.track_artists = vec!["A".to_string()];
+        other.artist = "A".to_string();
+        song.path = PathBuf::from("album/01.flac");
+        other.path = PathBuf::from("album/02.flac");
+
+        let db = Database::from_songs(vec![song, other], Vec::new(), &settings("VA", true));
+        assert_eq!(db.artists(), ["A", "B"]);
+        let folder = Some(Path::new("album"));
+        assert_eq!(db.album("A", "album", folder).unwrap().songs.len(), 2);
+        assert_eq!(db.album("B", "album", folder).unwrap().songs.len(), 1);
+    }
+
+    #[test]
+    fn lookups() {
+        let song = Song::example();
+        let id = song.id;
+        let db = Database::from_songs(vec![song], Vec::new(), &settings("VA", true));
+
+        assert_eq!(db.song(id).unwrap().title, "title");
+        assert_eq!(db.album("artist", "album", None).unwrap().songs.len(), 1);
+        assert!(db.song(SongId::new(Path::new("missing"))).is_none());
+        assert!(db.album("artist", "missing", None).is_none());
+        assert!(db.albums_by_artist("missing").is_none());
+
+        let mut queue = vec![Song {
+            path: PathBuf::from("old"),
+            title: "old title".to_string(),
+            ..Song::example()
+        }];
+        assert!(db.refresh(&mut queue));
+        assert_eq!(queue[0].path, Path::new("path"));
+        assert!(!db.refresh(&mut queue));
+    }
+
+    #[test]
+    fn queries() {
+        let song = |title: &str, album: &str, year: u16, genre: &str, track: u16| Song {
+            title: title.to_string(),
+            album: album.to_string(),
+            year,
+            genres: vec![genre.to_string()],
+            track_number: track,
+            path: PathBuf::from(format!("{album}/{track}.flac")),
+            id: SongId(track as u64),
+            ..Song::example()
+        };
+        let songs = vec![
+            song("Airbag", "OK Computer", 1997, "Rock", 1),
+            song("Paranoid Android", "OK Computer", 1997, "Rock", 2),
+            song("Paranoid Android (Live)", "Live", 2001, "Rock", 3),
+            song("So What", "Kind of Blue", 1959, "Jazz", 4),
+        ];
+        let db = Database::from_songs(songs, Vec::new(), &settings("VA", true));
+        let titles = |query: &str| -> Vec<String> {
+            db.search(query, &PlayCounts::default())
+                .unwrap()
+                .into_iter()
+                .map(|result| result.item.name().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            titles(r#"album:"ok computer" year:>1995"#),
+            ["Airbag", "Paranoid Android"]
+        );
+        assert_eq!(titles("paranoid -live"), ["Paranoid Android"]);
+        assert_eq!(titles("genre:jazz OR track:1"), ["So What", "Airbag"]);
+        assert_eq!(
+            titles("artist:artist year:>=2001"),
+            ["Paranoid Android (Live)"]
+        );
+
+        let result = &db
+            .search("paranoid year:<2000", &PlayCounts::default())
+            .unwrap()[0];
+        assert_eq!(result.highlights, vec![0..8]);
+        assert!(db.search("year:>", &PlayCounts::default()).is_err());
+    }
+
+    #[test]
+    fn sorting() {
+        let song = |artist: &str, album: &str| Song {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            album_artist: artist.to_string(),
+            track_artists: vec![artist.to_string()],
+            path: PathBuf::from(format!("{artist}/{album}/01.flac")),
+            ..Song::example()
+        };
+
+        let mut bowie = song("David Bowie", "Low");
+        bowie.album_artist_sort = "Bowie, David".to_string();
+        let mut heroes = song("David Bowie", "\"Heroes\"");
+        heroes.album_sort = "Heroes".to_string();
+
+        let songs = vec![
+            song("The Beatles", "The White Album"),
+            song("The Beatles", "Abbey Road"),
+            song("Zappa", "Hot Rats"),
+            song("Émilie Simon", "Végétal"),
+            song("abba", "Arrival"),
+            bowie,
+            heroes,
+        ];
+
+        let db = Database::from_songs(songs.clone(), Vec::new(), &settings("VA", true));
+        assert_eq!(
+            db.artists(),
+            [
+                "abba",
+                "The Beatles",
+                "David Bowie",
+                "Émilie Simon",
+                "Zappa"
+            ]
+        );
+        let titles: Vec<_> = db
+            .albums_by_artist("The Beatles")
+            .unwrap()
+            .iter()
+            .map(|a| &a.title)
+            .collect();
+        assert_eq!(titles, ["Abbey Road", "The White Album"]);
+        let titles: Vec<_> = db
+            .albums_by_artist("David Bowie")
+            .unwrap()
+            .iter()
+            .map(|a| &a.title)
+            .collect();
+        assert_eq!(titles, ["\"Heroes\"", "Low"]);
+
+        let db = Database::from_songs(songs, Vec::new(), &settings("VA", false));
+        assert_eq!(
+            db.artists(),
+            [
+                "abba",
+                "David Bowie",
+                "Émilie Simon",
+                "The Beatles",
+                "Zappa"
+            ]
+        );
+    }
+
+    #[test]
+    fn played_artists() {
+        let song = |artist: &str, id: u64| Song {
+            artist: artist.to_string(),
+            album_artist: artist.to_string(),
+            track_artists: vec![artist.to_string()],
+            path: PathBuf::from(format!("{artist}/01.flac")),
+            id: SongId(id),
+            ..Song::example()
+        };
+        let songs = vec![song("Band A", 1), song("Band B", 2)];
+        let mut db = Database::from_songs(songs, Vec::new(), &settings("VA", true));
+        let first =
+            |db: &Database, plays: &PlayCounts| db.search("band", plays).unwrap()[0].item.clone();
+
+        let mut plays = PlayCounts::default();
+        db.count_plays(&plays);
+        assert_eq!(first(&db, &plays), Item::Artist("Band A".to_string()));
+        plays.played(SongId(2));
+        db.played(SongId(2));
+        assert_eq!(first(&db, &plays), Item::Artist("Band B".to_string()));
+
+        //Totals counted from scratch agree.
+        db.count_plays(&plays);
+        assert_eq!(first(&db, &plays), Item::Artist("Band B".to_string()));
     }
 }
 
-const MIN_ACCURACY: f64 = 0.70;
+///Songs returned by a query with fields.
+pub const MAX_MATCHES: usize = 1000;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Item {
-    ///(Artist, Album, Name, Disc Number, Track Number)
-    Song((String, String, String, u8, u8)),
-    ///(Artist, Album)
-    Album((String, String)),
+    ///(Artist, Album, Name, Disc Number, Track Number, ID)
+    Song((String, String, String, u16, u16, SongId)),
+    ///(Artist, Album, Folder) see `Album::folder`.
+    Album((String, String, Option<PathBuf>)),
     ///(Artist)
     Artist(String),
 }
 
-///https://en.wikipedia.org/wiki/Jaro%E2%80%93Winkler_distance
-fn jaro(query: &str, input: Item) -> Result<(Item, f64), (Item, f64)> {
-    let str = match input {
-        Item::Artist(ref artist) => artist,
-        Item::Album((_, ref album)) => album,
-        Item::Song((_, _, ref song, _, _)) => song,
-    };
-    let acc = strsim::jaro_winkler(query, &str.to_lowercase());
-    if acc > MIN_ACCURACY {
-        Ok((input, acc))
-    } else {
-        Err((input, acc))
+impl Item {
+    ///The name that's searched.
+    pub fn name(&self) -> &str {
+        match self {
+            Item::Artist(artist) => artist,
+            Item::Album((_, album, _)) => album,
+            Item::Song((_, _, song, _, _, _)) => song,
+        }
     }
 }
 
+///The artist a song is filed under.
+///
+///Songs without an album artist use their first track artist, so "A feat. B" is filed under A.
+fn primary_artist(song: &Song) -> &str {
+    match song.track_artists.first() {
+        Some(artist) if song.album_artist.is_empty() => artist,
+        _ => &song.artist,
+    }
+}
+
+///The folder an album is in, disc folders like `CD1` or `Disc 2` are part of the folder above.
+pub fn album_folder(path: &Path) -> Option<&Path> {
+    let folder = path.parent()?;
+    match folder.file_name() {
+        Some(name) if is_disc_folder(&name.to_string_lossy()) => folder.parent(),
+        _ => Some(folder),
+    }
+}
+
+///`CD1`, `Disc 2`, `disk_03 - Bonus` and so on.
+fn is_disc_folder(name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+    let Some(rest) = ["cd", "disc", "disk"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+    else {
+        return false;
+    };
+    let rest = rest.trim_start_matches([' ', '_', '-', '.']);
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && !rest[digits..].starts_with(char::is_alphanumeric)
+}
+
 //I feel like Box<[String, Box<Album>]> might have been a better choice.
 pub struct Database {
     btree: BTreeMap<String, Vec<Album>>,
+    ///Artist that compilations are listed under.
+    various_artists: String,
+    ///The collation key each artist is sorted by.
+    sort_keys: HashMap<String, String>,
+    ///Where each song is stored: (artist, album index, song index).
+    ids: HashMap<SongId, (String, usize, usize)>,
+    search: SearchIndex,
+    ///Lyrics and comments of the songs that have them, see `Text`.
+    text: HashMap<SongId, Text>,
+    ///Whether Cyrillic and Greek names can be searched with Latin letters.
+    transliterate: bool,
+    ///How often the songs of each artist and album were played, see `count_plays`.
+    plays: HashMap<Item, u32>,
     pub len: usize,
+    ///Lines in the database that could not be read.
+    pub errors: Vec<String>,
 }
 
 impl Database {
     ///Read the database from disk and load it into memory.
-    pub fn new() -> Self {
+    ///
+    ///Compilations are listed under `Settings::various_artists` and multi-valued tags
+    ///are split with `Settings::separators`.
+    pub fn new(settings: &Settings) -> Self {
         mini::profile!();
+
         let bytes = match fs::read(database_path()) {
             Ok(bytes) => bytes,
             Err(error) => match error.kind() {
@@ -65,19 +414,88 @@ impl Database {
                 _ => panic!("{error}"),
             },
         };
-        let songs: Vec<Song> = unsafe { from_utf8_unchecked(&bytes) }
-            .lines()
-            .flat_map(Song::deserialize)
+        let string = unsafe { from_utf8_unchecked(&bytes) };
+        let (songs, errors) = match db::deserialize_database(string) {
+            Ok((songs, errors)) => {
+                //Upgrade the database so it only has to be migrated once.
+                //Lines that couldn't be read would be lost, so the old file is kept until a rescan.
+                if !bytes.is_empty() && db::is_outdated(string) && errors.is_empty() {
+                    if let Err(err) = db::save(&songs) {
+                        log!("Failed to upgrade the database: {err}");
+                    }
+                }
+                (songs, errors)
+            }
+            Err(err) => (Vec::new(), vec![err.to_string()]),
+        };
+
+        let mut db = Self::from_songs(songs, errors, settings);
+        db.count_plays(&PlayCounts::load());
+        db
+    }
+
+    fn from_songs(mut songs: Vec<Song>, errors: Vec<String>, settings: &Settings) -> Self {
+        let various_artists = settings.various_artists.as_str();
+        let ignore_articles = settings.ignore_articles;
+        let transliterate = settings.transliterate;
+        for song in &mut songs {
+            song.split_tags(&settings.separators);
+        }
+        let text: HashMap<SongId, Text> = songs
+            .par_iter()
+            .filter(|song| !song.lyrics.is_empty() || !song.comment.is_empty())
+            .map(|song| (song.id, Text::new(song, transliterate)))
             .collect();
 
         let len = songs.len();
         let mut btree: BTreeMap<String, Vec<Album>> = BTreeMap::new();
-        let mut albums: BTreeMap<(String, String), Vec<Song>> = BTreeMap::new();
+        //(artist, album, folder) where the folder is only used for songs without an album artist.
+        let mut albums: BTreeMap<(String, String, Option<PathBuf>), Vec<Song>> = BTreeMap::new();
+
+        //Without an album artist, albums are grouped by title and folder.
+        //Albums with several artists are compilations.
+        //Compilations are grouped by folder too, they often share a title.
+        let untagged = |song: &Song| song.album_artist.is_empty() && !song.compilation;
+        let mut artists: HashMap<(&str, Option<&Path>), HashSet<&str>> = HashMap::new();
+        for song in songs.iter().filter(|song| untagged(song)) {
+            artists
+                .entry((&song.album, album_folder(&song.path)))
+                .or_default()
+                .insert(primary_artist(song));
+        }
 
-        //Add songs to albums.
-        for song in songs.into_iter() {
+        let keys: Vec<(String, Option<PathBuf>)> = songs
+            .iter()
+            .map(|song| {
+                let folder = (untagged(song) || song.compilation)
+                    .then(|| album_folder(&song.path))
+                    .flatten();
+                let is_compilation = song.compilation
+                    || (untagged(song) && artists[&(song.album.as_str(), folder)].len() > 1);
+                let artist = if is_compilation {
+                    various_artists.to_string()
+                } else {
+                    primary_artist(song).to_string()
+                };
+                (artist, folder.map(Path::to_path_buf))
+            })
+            .collect();
+
+        //Add songs to albums, songs are also listed under every artist they credit.
+        //The artist a song is filed under is the one it's searched under.
+        let mut homes: HashMap<SongId, String> = HashMap::new();
+        for (song, (artist, folder)) in songs.into_iter().zip(keys) {
+            homes.insert(song.id, artist.clone());
+            for credit in &song.track_artists {
+                if *credit != artist {
+                    albums
+                        .entry((credit.clone(), song.album.clone(), folder.clone()))
+                        .or_default()
+                        .push(song.clone());
+                }
+            }
             albums
-                .entry((song.artist.clone(), song.album.clone()))
+                .entry((artist, song.album.clone(), folder))
                 .or_default()
                 .push(song);
         }
@@ -94,139 +512,243 @@ impl Database {
         });
 
         //Add albums to artists.
-        for ((artist, title), songs) in albums {
-            btree
-                .entry(artist)
-                .or_default()
-                .push(Album { title, songs });
+        let mut sort_keys: HashMap<String, String> = HashMap::new();
+        for ((artist, title, folder), songs) in albums {
+            //Sort tags are already in the right order, so articles are kept.
+            //Credited artists don't have sort tags of their own.
+            let sort_tag = songs
+                .iter()
+                .filter(|song| primary_artist(song) == artist)
+                .find_map(|song| {
+                    let tag = if song.album_artist.is_empty() {
+                        &song.artist_sort
+                    } else {
+                        &song.album_artist_sort
+                    };
+                    (!tag.is_empty()).then_some(tag)
+                });
+            match (sort_keys.get(&artist), sort_tag) {
+                (None, None) => {
+                    let key = collate::sort_key(&artist, ignore_articles);
+                    sort_keys.insert(artist.clone(), key);
+                }
+                (_, Some(tag)) => {
+                    sort_keys.insert(artist.clone(), collate::sort_key(tag, false));
+                }
+                (Some(_), None) => {}
+            }
+
+            btree.entry(artist).or_default().push(Album {
+                title,
+                songs,
+                folder,
+            });
         }
 
         //Sort albums.
         btree.iter_mut().for_each(|(_, albums)| {
-            albums.sort_unstable_by_key(|album| album.title.to_ascii_lowercase());
+            albums.sort_by_cached_key(|album| {
+                let key = match album.songs.iter().find(|song| !song.album_sort.is_empty()) {
+                    Some(song) => collate::sort_key(&song.album_sort, false),
+                    None => collate::sort_key(&album.title, ignore_articles),
+                };
+                (key, album.title.clone(), album.folder.clone())
+            });
         });
 
-        Self { btree, len }
+        let mut ids = HashMap::new();
+        let mut items = Vec::new();
+        for (artist, albums) in &btree {
+            items.push(Item::Artist(artist.clone()));
+            for (i, album) in albums.iter().enumerate() {
+                items.push(Item::Album((
+                    artist.clone(),
+                    album.title.clone(),
+                    album.folder.clone(),
+                )));
+                for (j, song) in album.songs.iter().enumerate() {
+                    //Songs listed under several artists are the same song, only index it once.
+                    if homes.get(&song.id) != Some(artist) {
+                        ids.entry(song.id).or_insert_with(|| (artist.clone(), i, j));
+                        continue;
+                    }
+                    ids.insert(song.id, (artist.clone(), i, j));
+                    items.push(Item::Song((
+                        artist.clone(),
+                        album.title.clone(),
+                        song.title.clone(),
+                        song.disc_number,
+                        song.track_number,
+                        song.id,
+                    )));
+                }
+            }
+        }
+
+        Self {
+            btree,
+            various_artists: various_artists.to_string(),
+            sort_keys,
+            ids,
+            search: SearchIndex::new(items, transliterate),
+            text,
+            transliterate,
+            plays: HashMap::new(),
+            len,
+            errors,
+        }
+    }
+
+    ///Sum the plays of every artist and album, so searches don't have to.
+    pub fn count_plays(&mut self, plays: &PlayCounts) {
+        self.plays.clear();
+        for (artist, albums) in &self.btree {
+            let mut total = 0;
+            for album in albums {
+                let count = album.songs.iter().map(|song| plays.count(song.id)).sum();
+                let key = (artist.clone(), album.title.clone(), album.folder.clone());
+                self.plays.insert(Item::Album(key), count);
+                total += count;
+            }
+            self.plays.insert(Item::Artist(artist.clone()), total);
+        }
+    }
+
+    ///Count a play of the song for its album and every artist it's listed under.
+    pub fn played(&mut self, id: SongId) {
+        let Some(song) = self.song(id) else {
+            return;
+        };
+        let (home, album, _) = &self.ids[&id];
+        let album = &self.btree[home][*album];
+        let (title, folder) = (album.title.clone(), album.folder.clone());
+
+        let mut artists = vec![home.clone()];
+        artists.extend(song.track_artists.iter().filter(|a| *a != home).cloned());
+        for artist in artists {
+            let key = (artist.clone(), title.clone(), folder.clone());
+            *self.plays.entry(Item::Album(key)).or_default() += 1;
+            *self.plays.entry(Item::Artist(artist)).or_default() += 1;
+        }
     }
 
-    ///Get all artist names.
+    ///Get all artist names, compilations are listed first.
     pub fn artists(&self) -> Vec<&String> {
         let mut v: Vec<_> = self.btree.keys().collect();
-        v.sort_unstable_by_key(|artist| artist.to_ascii_lowercase());
+        v.sort_unstable_by_key(|artist| {
+            (
+                **artist != self.various_artists,
+                self.sort_keys.get(*artist),
+                *artist,
+            )
+        });
         v
     }
 
     ///Get all albums by an artist.
-    pub fn albums_by_artist(&self, artist: &str) -> &[Album] {
-        self.btree.get(artist).unwrap()
+    pub fn albums_by_artist(&self, artist: &str) -> Option<&[Album]> {
+        self.btree.get(artist).map(Vec::as_slice)
     }
 
-    ///Get an album by artist and album name.
-    pub fn album(&self, artist: &str, album: &str) -> &Album {
-        if let Some(albums) = self.btree.get(artist) {
-            for al in albums {
-                if album == al.title {
-                    return al;
-                }
-            }
-        }
-        panic!("Could not find album {} {}", artist, album);
+    ///Get an album by artist, album name and folder, see `Album::folder`.
+    pub fn album(&self, artist: &str, album: &str, folder: Option<&Path>) -> Option<&Album> {
+        self.btree
+            .get(artist)?
+            .iter()
+            .find(|al| al.title == album && al.folder.as_deref() == folder)
     }
 
     ///Get an individual song in the database.
-    pub fn song(&self, artist: &str, album: &str, disc: u8, number: u8) -> &Song {
-        for al in self.btree.get(artist).unwrap() {
-            if al.title == album {
-                for song in &al.songs {
-                    if song.disc_number == disc && song.track_number == number {
-                        return song;
-                    }
-                }
-            }
-        }
-        unreachable!();
-    }
-
-    ///Search the database and return the 25 most accurate matches.
-    pub fn search(&self, query: &str) -> Vec<Item> {
-        const MAX: usize = 40;
-
-        let query = query.to_lowercase();
-        let mut results = Vec::new();
-
-        for (artist, albums) in self.btree.iter() {
-            for album in albums.iter() {
-                for song in album.songs.iter() {
-                    results.push(jaro(
-                        &query,
-                        Item::Song((
-                            song.artist.clone(),
-                            song.album.clone(),
-                            song.title.clone(),
-                            song.disc_number,
-                            song.track_number,
-                        )),
-                    ));
+    pub fn song(&self, id: SongId) -> Option<&Song> {
+        let (artist, album, song) = self.ids.get(&id)?;
+        self.btree.get(artist)?.get(*album)?.songs.get(*song)
+    }
+
+    ///Update songs kept outside the database, like the queue and playlists, to match it.
+    ///
+    ///Songs are matched by ID so moved files and edited tags are picked up.
+    ///Lyrics and comments aren't copied, look them up with `song` instead.
+    ///Returns true if any song changed.
+    pub fn refresh(&self, songs: &mut [Song]) -> bool {
+        let mut changed = false;
+        for song in songs {
+            if let Some(new) = self.song(song.id) {
+                let new = Song {
+                    lyrics: String::new(),
+                    comment: String::new(),
+                    ..new.clone()
+                };
+                if new != *song {
+                    *song = new;
+                    changed = true;
                 }
-                results.push(jaro(
-                    &query,
-                    Item::Album((artist.clone(), album.title.clone())),
-                ));
             }
-            results.push(jaro(&query, Item::Artist(artist.clone())));
         }
+        changed
+    }
 
-        if query.is_empty() {
-            return results
-                .into_iter()
-                .take(MAX)
-                .map(|item| match item {
-                    Ok((item, _)) => item,
-                    Err((item, _)) => item,
-                })
-                .collect();
+    ///Search the database and return the most accurate matches.
+    ///
+    ///Plain queries match artists, albums and songs,
+    ///queries with fields, exclusions or `OR` only match songs.
+    ///
+    ///Often played songs, and the albums and artists they're on, rank slightly higher.
+    pub fn search(&self, query: &str, plays: &PlayCounts) -> Result<Vec<SearchResult>, ParseError> {
+        let query = Query::parse(query, self.transliterate)?;
+        if query.is_plain() {
+            return Ok(self.search.search(&query.text(), |item| match item {
+                Item::Song((_, _, _, _, _, id)) => plays.count(*id),
+                _ => self.plays.get(item).copied().unwrap_or(0),
+            }));
         }
 
-        let mut results: Vec<(Item, f64)> = results.into_iter().flatten().collect();
-
-        //Sort results by score.
-        results.sort_unstable_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+        let songs: Vec<(&String, &Album, &Song)> = self
+            .ids
+            .values()
+            .filter_map(|(artist, album, song)| {
+                let album = self.btree.get(artist)?.get(*album)?;
+                Some((artist, album, album.songs.get(*song)?))
+            })
+            .collect();
 
-        if results.len() > MAX {
-            //Remove the less accurate results.
-            unsafe {
-                results.set_len(MAX);
-            }
-        }
+        let empty = Text::default();
+        let mut results: Vec<(f64, &String, &Album, &Song)> = songs
+            .into_par_iter()
+            .filter_map(|(artist, album, song)| {
+                let text = self.text.get(&song.id).unwrap_or(&empty);
+                let score = query.score(song, artist, text, &self.search)?
+                    + search::play_boost(plays.count(song.id));
+                Some((score, artist, album, song))
+            })
+            .collect();
 
-        results.sort_unstable_by(|(item_1, score_1), (item_2, score_2)| {
-            if score_1 == score_2 {
-                match item_1 {
-                    Item::Artist(_) => match item_2 {
-                        Item::Song(_) | Item::Album(_) => Ordering::Less,
-                        Item::Artist(_) => Ordering::Equal,
-                    },
-                    Item::Album(_) => match item_2 {
-                        Item::Song(_) => Ordering::Less,
-                        Item::Album(_) => Ordering::Equal,
-                        Item::Artist(_) => Ordering::Greater,
-                    },
-                    Item::Song((_, _, _, disc_a, number_a)) => match item_2 {
-                        Item::Song((_, _, _, disc_b, number_b)) => match disc_a.cmp(disc_b) {
-                            Ordering::Less => Ordering::Less,
-                            Ordering::Equal => number_a.cmp(number_b),
-                            Ordering::Greater => Ordering::Greater,
-                        },
-                        Item::Album(_) | Item::Artist(_) => Ordering::Greater,
-                    },
-                }
-            } else if score_2 > score_1 {
-                Ordering::Equal
-            } else {
-                Ordering::Less
-            }
+        results.sort_unstable_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| self.sort_keys.get(a.1).cmp(&self.sort_keys.get(b.1)))
+                .then_with(|| a.1.cmp(b.1))
+                .then_with(|| a.2.title.cmp(&b.2.title))
+                .then_with(|| {
+                    (a.3.disc_number, a.3.track_number).cmp(&(b.3.disc_number, b.3.track_number))
+                })
         });
+        results.truncate(MAX_MATCHES);
 
-        results.into_iter().map(|(item, _)| item).collect()
+        let text = query.text();
+        Ok(results
+            .into_iter()
+            .map(|(score, artist, album, song)| SearchResult {
+                item: Item::Song((
+                    artist.clone(),
+                    album.title.clone(),
+                    song.title.clone(),
+                    song.disc_number,
+                    song.track_number,
+                    song.id,
+                )),
+                score,
+                highlights: search::highlights(&song.title, &text, self.transliterate),
+                snippet: query.snippet(self.text.get(&song.id).unwrap_or(&empty)),
+            })
+            .collect())
     }
 }
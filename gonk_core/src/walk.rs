@@ -0,0 +1,318 @@
+//! Portable parallel directory walker.
+//!
+//! - Every folder is read on the rayon thread pool.
+//! - Symbolic links are followed, folders that were already visited are skipped so link loops terminate.
+//! - Hidden files and folders are skipped.
+//! - Files matching a pattern in a `.gonkignore` file are skipped.
+//!
+//! Ignore files apply to the folder they're in and everything below it.
+//! Each line is a pattern, `*` matches any number of characters and `?` matches one.
+//! Patterns containing `/` match the path relative to the ignore file, otherwise just the name.
+//! A trailing `/` only matches folders. Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Skip demos and anything in a scans folder.
+//! *demo*
+//! scans/
+//! ```
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+pub const IGNORE_FILE: &str = ".gonkignore";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    ///The folder containing the ignore file.
+    base: PathBuf,
+    glob: String,
+    folders_only: bool,
+}
+
+impl Pattern {
+    pub fn new(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (glob, folders_only) = match line.strip_suffix('/') {
+            Some(glob) => (glob, true),
+            None => (line, false),
+        };
+
+        Some(Self {
+            base: base.to_path_buf(),
+            glob: glob.trim_start_matches('/').to_string(),
+            folders_only,
+        })
+    }
+
+    pub fn matches(&self, path: &Path, is_folder: bool) -> bool {
+        if self.folders_only && !is_folder {
+            return false;
+        }
+
+        if self.glob.contains('/') {
+            let Ok(relative) = path.strip_prefix(&self.base) else {
+                return false;
+            };
+            let relative: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            glob(&self.glob, &relative.join("/"))
+        } else {
+            match path.file_name() {
+                Some(name) => glob(&self.glob, &name.to_string_lossy()),
+                None => false,
+            }
+        }
+    }
+}
+
+///Match `text` against a pattern with `*` and `?` wildcards.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    //The last `*` and the text position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                //Let the `*` match one more character.
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+///Read the patterns in a folder's ignore file.
+fn ignore_file(folder: &Path) -> Vec<Pattern> {
+    match fs::read_to_string(folder.join(IGNORE_FILE)) {
+        Ok(string) => string
+            .lines()
+            .filter_map(|line| Pattern::new(folder, line))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(windows)]
+fn is_hidden(name: &str, metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    name.starts_with('.') || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[cfg(not(windows))]
+fn is_hidden(name: &str, _: &fs::Metadata) -> bool {
+    name.starts_with('.')
+}
+
+struct Walk<'a> {
+    extensions: &'a [&'a str],
+    files: Mutex<Vec<PathBuf>>,
+    ///Canonical paths of every folder read so far.
+    visited: Mutex<HashSet<PathBuf>>,
+}
+
+impl Walk<'_> {
+    fn folder<'s>(
+        &'s self,
+        scope: &rayon::Scope<'s>,
+        folder: PathBuf,
+        patterns: Arc<Vec<Pattern>>,
+    ) {
+        //Symbolic links can point back at a parent folder.
+        let Ok(canonical) = fs::canonicalize(&folder) else {
+            return;
+        };
+        if !self.visited.lock().unwrap().insert(canonical) {
+            return;
+        }
+
+        let Ok(entries) = fs::read_dir(&folder) else {
+            return;
+        };
+
+        let local = ignore_file(&folder);
+        let patterns = if local.is_empty() {
+            patterns
+        } else {
+            Arc::new(patterns.iter().cloned().chain(local).collect())
+        };
+
+        let mut files = Vec::new();
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            //Follows symbolic links.
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let is_folder = metadata.is_dir();
+
+            if is_hidden(&entry.file_name().to_string_lossy(), &metadata)
+                || patterns.iter().any(|p| p.matches(&path, is_folder))
+            {
+                continue;
+            }
+
+            if is_folder {
+                let patterns = patterns.clone();
+                scope.spawn(move |scope| self.folder(scope, path, patterns));
+            } else if self.is_included(&path) {
+                files.push(path);
+            }
+        }
+
+        if !files.is_empty() {
+            self.files.lock().unwrap().extend(files);
+        }
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        match path.extension().and_then(|ex| ex.to_str()) {
+            Some(ex) => self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ex)),
+            None => false,
+        }
+    }
+}
+
+///Every file inside `root` with one of the given extensions, or every file if `extensions` is empty.
+///
+///The files are sorted.
+pub fn walk<P: AsRef<Path>>(root: P, extensions: &[&str]) -> Vec<PathBuf> {
+    let walk = Walk {
+        extensions,
+        files: Mutex::new(Vec::new()),
+        visited: Mutex::new(HashSet::new()),
+    };
+
+    let root = root.as_ref().to_path_buf();
+    rayon::scope(|scope| walk.folder(scope, root, Arc::new(Vec::new())));
+
+    let mut files = walk.files.into_inner().unwrap();
+    files.sort_unstable();
+    files
+}
+
+///Would `walk(root, ..)` skip `path`, because it or one of its folders is hidden or ignored.
+///
+///Paths that no longer exist are checked by name.
+pub fn is_ignored(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return true;
+    };
+    let components: Vec<_> = relative.components().collect();
+    let mut patterns = Vec::new();
+    let mut current = root.to_path_buf();
+
+    for (i, component) in components.iter().enumerate() {
+        patterns.extend(ignore_file(&current));
+        current.push(component);
+
+        let name = component.as_os_str().to_string_lossy();
+        let hidden = match fs::metadata(&current) {
+            Ok(metadata) => is_hidden(&name, &metadata),
+            Err(_) => name.starts_with('.'),
+        };
+        let is_folder = i + 1 < components.len() || current.is_dir();
+
+        if hidden || patterns.iter().any(|p| p.matches(&current, is_folder)) {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(glob("*.flac", "song.flac"));
+        assert!(glob("*demo*", "01 demo take.mp3"));
+        assert!(glob("track??.mp3", "track01.mp3"));
+        assert!(!glob("*.flac", "song.mp3"));
+        assert!(!glob("track?.mp3", "track01.mp3"));
+
+        let base = Path::new("music");
+        let scans = Pattern::new(base, "scans/").unwrap();
+        assert!(scans.matches(&base.join("album").join("scans"), true));
+        assert!(!scans.matches(&base.join("album").join("scans"), false));
+
+        let nested = Pattern::new(base, "/artist/*/bonus").unwrap();
+        assert!(nested.matches(&base.join("artist").join("album").join("bonus"), true));
+        assert!(!nested.matches(&base.join("bonus"), true));
+
+        assert!(Pattern::new(base, "# comment").is_none());
+        assert!(Pattern::new(base, "  ").is_none());
+    }
+
+    #[test]
+    fn walk_folder() {
+        let dir = std::env::temp_dir().join("gonk_walk");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("album").join("scans")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+
+        fs::write(dir.join("album").join("01.flac"), "").unwrap();
+        fs::write(dir.join("album").join("02.MP3"), "").unwrap();
+        fs::write(dir.join("album").join("demo.flac"), "").unwrap();
+        fs::write(dir.join("album").join("cover.jpg"), "").unwrap();
+        fs::write(dir.join("album").join("scans").join("03.flac"), "").unwrap();
+        fs::write(dir.join(".hidden").join("04.flac"), "").unwrap();
+        fs::write(dir.join(IGNORE_FILE), "scans/\ndemo*").unwrap();
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("album").join("loop")).unwrap();
+
+        let files = walk(&dir, &["flac", "mp3"]);
+        assert_eq!(
+            files,
+            [
+                dir.join("album").join("01.flac"),
+                dir.join("album").join("02.MP3")
+            ]
+        );
+
+        assert!(!is_ignored(&dir, &dir.join("album").join("01.flac")));
+        assert!(is_ignored(&dir, &dir.join("album").join("demo.flac")));
+        assert!(is_ignored(
+            &dir,
+            &dir.join("album").join("scans").join("03.flac")
+        ));
+        assert!(is_ignored(&dir, &dir.join(".hidden").join("04.flac")));
+        assert!(is_ignored(&dir, &dir.join("album").join(".removed.flac")));
+        assert!(is_ignored(&dir, Path::new("elsewhere/01.flac")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! Watch the music folder for changes.
+//!
+//! Uses inotify on Linux, other platforms aren't supported yet.
+//!
+//! Events are debounced, a batch is only sent once the folder has been quiet for `DEBOUNCE`.
+//! That way copying an album is applied once instead of once per file.
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    sync::mpsc::Receiver,
+    time::Duration,
+};
+
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    ///A file was created or modified, or a folder was created.
+    Changed(PathBuf),
+    ///A file or folder was removed, or a music folder was removed or moved away.
+    Removed(PathBuf),
+    Renamed(PathBuf, PathBuf),
+    ///Events were lost, every music folder needs to be scanned again.
+    Overflow,
+}
+
+///Add an event to a batch, dropping older events it makes redundant.
+pub fn push(events: &mut Vec<Event>, event: Event) {
+    match &event {
+        Event::Changed(path) | Event::Removed(path) => {
+            events.retain(|e| !matches!(e, Event::Changed(p) if p == path))
+        }
+        Event::Renamed(..) => {}
+        Event::Overflow => events.clear(),
+    }
+    events.push(event);
+}
+
+///Watch folders and all of their sub-folders.
+///
+///The watcher stops once the receiver is dropped.
+#[cfg(target_os = "linux")]
+pub fn watch<P: AsRef<Path>>(folders: &[P]) -> Result<Receiver<Vec<Event>>, Box<dyn Error>> {
+    inotify::watch(folders)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch<P: AsRef<Path>>(_: &[P]) -> Result<Receiver<Vec<Event>>, Box<dyn Error>> {
+    Err("Watching folders is only supported on Linux.")?
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use super::*;
+    use libc::{
+        c_int, close, inotify_add_watch, inotify_event, inotify_init1, poll, pollfd, read,
+        IN_CLOEXEC, IN_CLOSE_WRITE, IN_CREATE, IN_DELETE, IN_DELETE_SELF, IN_IGNORED, IN_ISDIR,
+        IN_MOVED_FROM, IN_MOVED_TO, IN_MOVE_SELF, IN_Q_OVERFLOW, POLLIN,
+    };
+    use std::{
+        collections::HashMap,
+        ffi::{CString, OsStr},
+        fs,
+        io::Error as IoError,
+        os::unix::ffi::OsStrExt,
+        sync::mpsc::{self, Sender},
+        thread,
+    };
+
+    const MASK: u32 = IN_CLOSE_WRITE
+        | IN_MOVED_FROM
+        | IN_MOVED_TO
+        | IN_CREATE
+        | IN_DELETE
+        | IN_DELETE_SELF
+        | IN_MOVE_SELF;
+
+    ///wd, mask, cookie and name length.
+    const EVENT_SIZE: usize = std::mem::size_of::<inotify_event>();
+
+    struct Inotify {
+        fd: c_int,
+        roots: Vec<PathBuf>,
+        ///Watch descriptors and the folder they watch.
+        folders: HashMap<c_int, PathBuf>,
+        ///Moves waiting for their `IN_MOVED_TO`, by cookie.
+        moves: HashMap<u32, PathBuf>,
+        events: Vec<Event>,
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe { close(self.fd) };
+        }
+    }
+
+    impl Inotify {
+        ///Watch a folder and every folder inside it.
+        fn add(&mut self, path: &Path) {
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                return;
+            };
+
+            let wd = unsafe { inotify_add_watch(self.fd, c_path.as_ptr(), MASK) };
+            if wd < 0 {
+                return;
+            }
+            //Renamed folders keep their watch descriptor.
+            self.folders.insert(wd, path.to_path_buf());
+
+            if let Ok(entries) = fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                        self.add(&entry.path());
+                    }
+                }
+            }
+        }
+
+        fn read(&mut self) -> Result<(), IoError> {
+            let mut buffer = [0u8; 4096];
+            let len = unsafe { read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+            if len < 0 {
+                return Err(IoError::last_os_error());
+            }
+
+            let buffer = &buffer[..len as usize];
+            let mut i = 0;
+            while i + EVENT_SIZE <= buffer.len() {
+                let u32_at = |i: usize| u32::from_ne_bytes(buffer[i..i + 4].try_into().unwrap());
+                let wd = u32_at(i) as c_int;
+                let mask = u32_at(i + 4);
+                let cookie = u32_at(i + 8);
+                let name_len = u32_at(i + 12) as usize;
+
+                //The name is padded with null bytes.
+                let Some(name) = buffer.get(i + EVENT_SIZE..i + EVENT_SIZE + name_len) else {
+                    break;
+                };
+                let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+                i += EVENT_SIZE + name_len;
+
+                if mask & IN_Q_OVERFLOW != 0 {
+                    push(&mut self.events, Event::Overflow);
+                    continue;
+                }
+
+                //The watch was removed because its folder is gone.
+                if mask & IN_IGNORED != 0 {
+                    self.folders.remove(&wd);
+                    continue;
+                }
+
+                let Some(folder) = self.folders.get(&wd) else {
+                    continue;
+                };
+
+                //Other folders are handled by the events of their parent.
+                if mask & (IN_DELETE_SELF | IN_MOVE_SELF) != 0 {
+                    if self.roots.contains(folder) {
+                        push(&mut self.events, Event::Removed(folder.clone()));
+                    }
+                    continue;
+                }
+                let path = folder.join(OsStr::from_bytes(name));
+                let is_dir = mask & IN_ISDIR != 0;
+
+                if mask & IN_MOVED_FROM != 0 {
+                    self.moves.insert(cookie, path);
+                } else if mask & IN_MOVED_TO != 0 {
+                    if is_dir {
+                        self.add(&path);
+                    }
+                    match self.moves.remove(&cookie) {
+                        Some(from) => push(&mut self.events, Event::Renamed(from, path)),
+                        //Moved in from outside the music folder.
+                        None => push(&mut self.events, Event::Changed(path)),
+                    }
+                } else if mask & IN_DELETE != 0 {
+                    push(&mut self.events, Event::Removed(path));
+                } else if mask & IN_CREATE != 0 {
+                    //New files are handled by `IN_CLOSE_WRITE` once they're written.
+                    if is_dir {
+                        self.add(&path);
+                        push(&mut self.events, Event::Changed(path));
+                    }
+                } else if mask & IN_CLOSE_WRITE != 0 {
+                    push(&mut self.events, Event::Changed(path));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn run(mut self, sender: Sender<Vec<Event>>) {
+            let timeout = DEBOUNCE.as_millis() as c_int;
+
+            loop {
+                let waiting = !self.events.is_empty() || !self.moves.is_empty();
+                let mut fd = pollfd {
+                    fd: self.fd,
+                    events: POLLIN,
+                    revents: 0,
+                };
+
+                let ready = unsafe { poll(&mut fd, 1, if waiting { timeout } else { -1 }) };
+
+                if ready < 0 {
+                    if IoError::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return;
+                }
+
+                if ready == 0 {
+                    //Files moved out of the music folder never get an `IN_MOVED_TO`.
+                    for (_, path) in self.moves.drain() {
+                        push(&mut self.events, Event::Removed(path));
+                    }
+
+                    if sender.send(std::mem::take(&mut self.events)).is_err() {
+                        return;
+                    }
+                    continue;
+                }
+
+                if self.read().is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn watch<P: AsRef<Path>>(folders: &[P]) -> Result<Receiver<Vec<Event>>, Box<dyn Error>> {
+        let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(IoError::last_os_error())?;
+        }
+
+        let mut inotify = Inotify {
+            fd,
+            roots: folders.iter().map(|f| f.as_ref().to_path_buf()).collect(),
+            folders: HashMap::new(),
+            moves: HashMap::new(),
+            events: Vec::new(),
+        };
+        for folder in folders {
+            inotify.add(folder.as_ref());
+        }
+
+        if inotify.folders.is_empty() {
+            return Err("Could not watch any music folders.")?;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || inotify.run(sender));
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounce() {
+        let mut events = Vec::new();
+        push(&mut events, Event::Changed(PathBuf::from("a")));
+        push(&mut events, Event::Changed(PathBuf::from("b")));
+        push(&mut events, Event::Changed(PathBuf::from("a")));
+        assert_eq!(
+            events,
+            [
+                Event::Changed(PathBuf::from("b")),
+                Event::Changed(PathBuf::from("a"))
+            ]
+        );
+
+        push(&mut events, Event::Removed(PathBuf::from("b")));
+        assert_eq!(
+            events,
+            [
+                Event::Changed(PathBuf::from("a")),
+                Event::Removed(PathBuf::from("b"))
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn inotify() {
+        let dir = std::env::temp_dir().join("gonk_watcher");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("album")).unwrap();
+
+        let receiver = watch(&[&dir]).unwrap();
+        let from = dir.join("album").join("song.flac");
+        let to = dir.join("album").join("renamed.flac");
+
+        std::fs::write(&from, b"").unwrap();
+        let events = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(events, [Event::Changed(from.clone())]);
+
+        std::fs::rename(&from, &to).unwrap();
+        let events = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(events, [Event::Renamed(from, to.clone())]);
+
+        std::fs::remove_file(&to).unwrap();
+        let events = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(events, [Event::Removed(to)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn root_removed() {
+        let dir = std::env::temp_dir().join("gonk_watcher_root");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let receiver = watch(&[&dir]).unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+        let events = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(events, [Event::Removed(dir)]);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut events = vec![Event::Changed(PathBuf::from("a"))];
+        push(&mut events, Event::Overflow);
+        push(&mut events, Event::Removed(PathBuf::from("b")));
+        assert_eq!(
+            events,
+            [Event::Overflow, Event::Removed(PathBuf::from("b"))]
+        );
+    }
+}
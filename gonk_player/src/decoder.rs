@@ -23,6 +23,10 @@ pub struct Symphonia {
     pub track: Track,
     pub elapsed: u64,
     pub duration: u64,
+    ///Timestamp where playback starts, non-zero for tracks split from a cue sheet.
+    pub start: u64,
+    ///Timestamp where playback stops, `None` plays until the end of the file.
+    pub end: Option<u64>,
     pub error_count: u8,
     pub done: bool,
 }
@@ -54,18 +58,45 @@ impl Symphonia {
             track,
             duration,
             elapsed: 0,
+            start: 0,
+            end: None,
             error_count: 0,
             done: false,
         })
     }
+    ///Only play part of the file, `start` and `end` are in seconds.
+    pub fn set_range(&mut self, start: f32, end: f32) {
+        let tb = self.track.codec_params.time_base.unwrap();
+        let timestamp = |secs: f32| {
+            let secs = Duration::from_secs_f32(secs);
+            tb.calc_timestamp(Time::new(
+                secs.as_secs(),
+                secs.subsec_nanos() as f64 / 1_000_000_000.0,
+            ))
+        };
+
+        self.start = timestamp(start).min(self.duration);
+        self.end = Some(timestamp(end).min(self.duration));
+        self.elapsed = self.start;
+
+        //Ignore errors.
+        let _ = self.format_reader.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: self.start,
+                track_id: self.track.id,
+            },
+        );
+    }
     pub fn elapsed(&self) -> Duration {
         let tb = self.track.codec_params.time_base.unwrap();
-        let time = tb.calc_time(self.elapsed);
+        let time = tb.calc_time(self.elapsed.saturating_sub(self.start));
         Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
     }
     pub fn duration(&self) -> Duration {
         let tb = self.track.codec_params.time_base.unwrap();
-        let time = tb.calc_time(self.duration);
+        let end = self.end.unwrap_or(self.duration);
+        let time = tb.calc_time(end.saturating_sub(self.start));
         Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
     }
     pub fn sample_rate(&self) -> u32 {
@@ -75,7 +106,11 @@ impl Symphonia {
     //I can't trust symphonia to provide accurate errors so it's not worth the hassle.
     //I could use pos + elapsed > duration but the duration isn't accurate.
     pub fn seek(&mut self, pos: f32) {
-        let pos = Duration::from_secs_f32(pos);
+        let tb = self.track.codec_params.time_base.unwrap();
+        let start = tb.calc_time(self.start);
+        let pos = Duration::from_secs_f32(pos)
+            + Duration::from_secs(start.seconds)
+            + Duration::from_secs_f64(start.frac);
 
         //Ignore errors.
         let _ = self.format_reader.seek(
@@ -118,10 +153,16 @@ impl Symphonia {
 
         self.elapsed = next_packet.ts();
 
-        //HACK: Sometimes the end of file error does not indicate the end of the file?
-        //The duration is a little bit longer than the maximum elapsed??
-        //The final packet will make the elapsed time move backwards???
-        if self.elapsed() + Duration::from_millis(250) > self.duration() {
+        if let Some(end) = self.end {
+            //Ranges end inside the file so the timestamps can be trusted.
+            if self.elapsed >= end {
+                self.done = true;
+                return None;
+            }
+        } else if self.elapsed() + Duration::from_millis(250) > self.duration() {
+            //HACK: Sometimes the end of file error does not indicate the end of the file?
+            //The duration is a little bit longer than the maximum elapsed??
+            //The final packet will make the elapsed time move backwards???
             self.done = true;
             return None;
         }
@@ -54,8 +54,8 @@ pub unsafe fn init_com() {
 #[derive(Debug, PartialEq)]
 enum Event {
     Stop,
-    //Path, Gain
-    Song(PathBuf, f32),
+    //Path, Gain, Start, End
+    Song(PathBuf, f32, f32, f32),
     Seek(f32),
     SeekBackward,
     SeekForward,
@@ -183,10 +183,10 @@ pub fn spawn_audio_threads(device: Device) {
                 std::thread::sleep(std::time::Duration::from_millis(8));
 
                 match EVENTS.pop() {
-                    Some(Event::Song(new_path, gain)) => {
+                    Some(Event::Song(new_path, gain, start, end)) => {
                         // info!("{} paused: {}", new_path.display(), PAUSED);
                         // info!("Gain: {} prod capacity: {}", gain, prod.capacity());
-                        let s = match Symphonia::new(&new_path) {
+                        let mut s = match Symphonia::new(&new_path) {
                             Ok(s) => s,
                             Err(e) => {
                                 gonk_core::log!(
@@ -199,6 +199,11 @@ pub fn spawn_audio_threads(device: Device) {
                             }
                         };
 
+                        //Tracks split from a cue sheet only play part of the file.
+                        if end != 0.0 {
+                            s.set_range(start, end);
+                        }
+
                         //We don't set the playback state here because it might be delayed.
                         SAMPLE_RATE = Some(s.sample_rate());
                         DURATION = s.duration();
@@ -444,7 +449,7 @@ pub fn play_path<P: AsRef<Path>>(path: P) {
     unsafe {
         PAUSED = false;
         ELAPSED = Duration::from_secs(0);
-        EVENTS.push(Event::Song(path.as_ref().to_path_buf(), 0.5));
+        EVENTS.push(Event::Song(path.as_ref().to_path_buf(), 0.5, 0.0, 0.0));
     }
 }
 
@@ -453,8 +458,10 @@ pub fn play_song(song: &Song) {
         PAUSED = false;
         ELAPSED = Duration::from_secs(0);
         EVENTS.push(Event::Song(
-            PathBuf::from(&song.path),
+            song.path.clone(),
             if song.gain == 0.0 { 0.5 } else { song.gain },
+            song.start,
+            song.end,
         ));
     }
 }